@@ -0,0 +1,73 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use four_in_a_row::game::{Agent, MinMaxAgent};
+use four_in_a_row::game_logic::{get_random_position, packed_board, GameState};
+
+// Agents keep their transposition table between moves, so every iteration starts a fresh one.
+
+fn min_max_next_move_benchmark(c: &mut Criterion) {
+    let gs = GameState::new();
+    c.bench_function("min_max next_move", |b| b.iter(|| MinMaxAgent::new().next_move(black_box(&gs))));
+}
+
+fn clone_vs_make_unmake_benchmark(c: &mut Criterion) {
+    let gs = GameState::new();
+    let mut group = c.benchmark_group("min_max clone vs make/unmake");
+    for depth in [3, 5, 7] {
+        group.bench_with_input(BenchmarkId::new("clone", depth), &gs, |b, gs| {
+            b.iter(|| MinMaxAgent::new_with_depth(depth).with_make_unmake(false).next_move(black_box(gs)))
+        });
+        group.bench_with_input(BenchmarkId::new("make/unmake", depth), &gs, |b, gs| {
+            b.iter(|| MinMaxAgent::new_with_depth(depth).next_move(black_box(gs)))
+        });
+    }
+    group.finish();
+}
+
+fn aspiration_window_benchmark(c: &mut Criterion) {
+    let gs = GameState::new();
+    let mut group = c.benchmark_group("min_max aspiration windows");
+    for depth in [5, 7] {
+        group.bench_with_input(BenchmarkId::new("full window", depth), &gs, |b, gs| {
+            b.iter(|| MinMaxAgent::new_with_depth(depth).next_move(black_box(gs)))
+        });
+        group.bench_with_input(BenchmarkId::new("aspiration", depth), &gs, |b, gs| {
+            b.iter(|| MinMaxAgent::new_with_depth(depth).with_aspiration_window(Some(1.0)).next_move(black_box(gs)))
+        });
+    }
+    group.finish();
+}
+
+fn killer_moves_benchmark(c: &mut Criterion) {
+    let gs = GameState::new();
+    let mut group = c.benchmark_group("min_max move ordering");
+    group.bench_function("column order", |b| b.iter(|| MinMaxAgent::new_with_depth(8).with_killer_moves(false).next_move(black_box(&gs))));
+    group.bench_function("killer moves", |b| b.iter(|| MinMaxAgent::new_with_depth(8).next_move(black_box(&gs))));
+    group.bench_function("center first", |b| b.iter(|| MinMaxAgent::new_with_depth(8).with_center_first(true).next_move(black_box(&gs))));
+    group.finish();
+}
+
+/// Every node of a search keys the transposition table on the canonical position. Building that
+/// position used to clone the board at every node; `canonical_key` packs it without one.
+fn table_key_benchmark(c: &mut Criterion) {
+    let positions: Vec<GameState> = (0..100).map(|i| get_random_position(&GameState::new(), i % 30)).collect();
+    let mut group = c.benchmark_group("min_max table keys");
+    group.bench_function("cloned canonical", |b| b.iter(|| positions.iter().map(|gs| {
+        let canonical = black_box(gs).canonical();
+        (canonical.zobrist(), packed_board(&canonical))
+    }).collect::<Vec<_>>()));
+    group.bench_function("canonical_key", |b| b.iter(|| positions.iter().map(|gs| black_box(gs).canonical_key()).collect::<Vec<_>>()));
+    group.finish();
+}
+
+/// Every node of a search used to collect a fresh list of its moves; now each depth fills the
+/// same list again.
+fn move_list_benchmark(c: &mut Criterion) {
+    let gs = GameState::new();
+    let mut group = c.benchmark_group("min_max move lists");
+    group.bench_function("fresh lists depth 7", |b| b.iter(|| MinMaxAgent::new_with_depth(7).with_reused_move_lists(false).next_move(black_box(&gs))));
+    group.bench_function("reused lists depth 7", |b| b.iter(|| MinMaxAgent::new_with_depth(7).next_move(black_box(&gs))));
+    group.finish();
+}
+
+criterion_group!(benches, min_max_next_move_benchmark, clone_vs_make_unmake_benchmark, aspiration_window_benchmark, killer_moves_benchmark, table_key_benchmark, move_list_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,16 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use four_in_a_row::game_logic::{get_random_position, ranked_moves, ranked_moves_parallel, EvalParams, GameState};
+use four_in_a_row::rng;
+
+fn ranked_moves_benchmark(c: &mut Criterion) {
+    rng::seed(0);
+    let gs = get_random_position(&GameState::new_from_board(vec![vec![0; 30]; 12]), 20);
+    let params = EvalParams::default();
+    let mut group = c.benchmark_group("ranked_moves on a 12x30 board");
+    group.bench_function("sequential", |b| b.iter(|| ranked_moves(black_box(&gs), &params)));
+    group.bench_function("parallel", |b| b.iter(|| ranked_moves_parallel(black_box(&gs), &params)));
+    group.finish();
+}
+
+criterion_group!(benches, ranked_moves_benchmark);
+criterion_main!(benches);
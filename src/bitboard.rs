@@ -0,0 +1,252 @@
+use std::sync::OnceLock;
+use crate::game_logic::{all_lines, center_bonus_from, centrality, GameResult, GameState, GravityDir, Player};
+
+const ROWS: usize = 6;
+const COLS: usize = 7;
+/// Each column takes one bit more than it has rows, so that shifting a line never wraps into the
+/// next column.
+const COL_BITS: usize = ROWS + 1;
+
+/// The standard 6x7 board packed into one bitmask per player, with bit `col * 7 + row` set for a
+/// disc `row` cells above the bottom. It is `Copy`, so searching it never touches the heap.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct BitBoard {
+    discs: [u64; 2],
+    heights: [u8; COLS],
+    turn: Player,
+    /// Worked out once whenever the position changes, so reading it is free.
+    result: Option<GameResult>,
+}
+
+fn index(player: Player) -> usize {
+    match player {
+        Player::P1 => 0,
+        Player::P2 => 1,
+    }
+}
+
+fn bit(row: usize, col: usize) -> u64 {
+    1 << (col * COL_BITS + ROWS - 1 - row)
+}
+
+fn has_four(discs: u64) -> bool {
+    [1, COL_BITS, COL_BITS - 1, COL_BITS + 1].iter().any(|&shift| {
+        let pairs = discs & (discs >> shift);
+        pairs & (pairs >> (2 * shift)) != 0
+    })
+}
+
+fn line_masks() -> &'static [u64] {
+    static MASKS: OnceLock<Vec<u64>> = OnceLock::new();
+    MASKS.get_or_init(|| all_lines(&GameState::new())
+        .map(|line| line.iter().fold(0, |mask, mov| mask | bit(mov.row(), mov.col())))
+        .collect())
+}
+
+impl BitBoard {
+    pub fn new() -> Self {
+        Self {
+            discs: [0; 2],
+            heights: [0; COLS],
+            turn: Player::P1,
+            result: None,
+        }
+    }
+
+    /// Converts a standard 6x7 connect-4 position, or `None` for other sizes, win lengths and boards
+    /// with disabled columns, blocked cells, Pop Out or other gravity, which the bitboard cannot
+    /// represent.
+    pub fn from_game_state(gs: &GameState) -> Option<Self> {
+        if gs.rows() != ROWS || gs.cols() != COLS || gs.win_len() != 4 || gs.has_blocked_cells() || gs.pop_out() || gs.gravity() != GravityDir::Down || (0..COLS).any(|col| gs.is_column_disabled(col)) {
+            return None;
+        }
+        let mut board = Self { turn: gs.turn(), ..Self::new() };
+        for col in 0..COLS {
+            for row in (0..ROWS).rev() {
+                if let Some(player) = gs.disc_at(row, col) {
+                    board.discs[index(player)] |= bit(row, col);
+                    board.heights[col] += 1;
+                }
+            }
+        }
+        board.result = board.find_result();
+        Some(board)
+    }
+
+    pub fn turn(&self) -> Player {
+        self.turn
+    }
+
+    pub fn legal_cols(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..COLS).filter(|&col| (self.heights[col] as usize) < ROWS)
+    }
+
+    /// Every legal column together with the position it leads to.
+    pub fn children(&self) -> impl Iterator<Item = (usize, Self)> + '_ {
+        self.legal_cols().map(move |col| (col, self.play(col).unwrap()))
+    }
+
+    /// Drops a disc for the side to move in `col`, or `None` if the column is full.
+    pub fn play(&self, col: usize) -> Option<Self> {
+        let height = self.heights[col] as usize;
+        if height >= ROWS {
+            return None;
+        }
+        let mut next = *self;
+        next.discs[index(self.turn)] |= 1 << (col * COL_BITS + height);
+        next.heights[col] += 1;
+        next.turn = match self.turn {
+            Player::P1 => Player::P2,
+            Player::P2 => Player::P1,
+        };
+        next.result = next.find_result();
+        Some(next)
+    }
+
+    pub fn result(&self) -> Option<GameResult> {
+        self.result
+    }
+
+    fn find_result(&self) -> Option<GameResult> {
+        for player in [Player::P1, Player::P2] {
+            if has_four(self.discs[index(player)]) {
+                return Some(GameResult::Win(player));
+            }
+        }
+        if self.heights.iter().all(|&height| height as usize == ROWS) {
+            return Some(GameResult::Draw);
+        }
+        None
+    }
+
+    /// The number of lines P1 and P2 could each still complete, as `potential_wins` counts them.
+    pub fn potential_wins(&self) -> (i32, i32) {
+        let open = |opponent: Player| line_masks().iter()
+            .filter(|&&mask| mask & self.discs[index(opponent)] == 0)
+            .count() as i32;
+        (open(Player::P2), open(Player::P1))
+    }
+
+    /// The open lines of P1 and P2 weighted by how many discs they hold, as
+    /// `weighted_potential_wins` counts them.
+    pub fn weighted_potential_wins(&self, weights: &[f32]) -> (f32, f32) {
+        let weigh = |player: Player, opponent: Player| line_masks().iter()
+            .filter(|&&mask| mask & self.discs[index(opponent)] == 0)
+            .filter_map(|&mask| {
+                let discs = (mask & self.discs[index(player)]).count_ones() as usize;
+                weights.get(discs).or(weights.last()).copied()
+            })
+            .sum();
+        (weigh(Player::P1, Player::P2), weigh(Player::P2, Player::P1))
+    }
+
+    /// P1's edge from central discs, as `center_bonus` computes it.
+    pub fn center_bonus(&self) -> f32 {
+        let sum = (0..COLS).map(|col| {
+            let column = ((1 << ROWS) - 1) << (col * COL_BITS);
+            let count = |player| (self.discs[index(player)] & column).count_ones() as i32;
+            centrality(col, COLS) * (count(Player::P1) - count(Player::P2))
+        }).sum();
+        center_bonus_from(sum, ROWS, COLS)
+    }
+
+    /// The same static evaluation as `utility`.
+    pub fn utility(&self) -> f32 {
+        match self.result() {
+            Some(GameResult::Win(Player::P1)) => f32::INFINITY,
+            Some(GameResult::Win(Player::P2)) => f32::NEG_INFINITY,
+            Some(GameResult::Draw) => 0.0,
+            None => {
+                let (p1, p2) = self.potential_wins();
+                (p1 - p2) as f32 + self.center_bonus()
+            }
+        }
+    }
+}
+
+impl Default for BitBoard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bitboard::BitBoard;
+    use crate::game_logic::{children, get_legal, get_random_position, play, result, utility, weighted_potential_wins, GameState};
+    use crate::rng;
+
+    #[test]
+    fn agrees_with_vec_board() {
+        rng::seed(252);
+        for i in 0..1000 {
+            let gs = get_random_position(&GameState::new(), i % 42);
+            let board = BitBoard::from_game_state(&gs).unwrap();
+            assert_eq!(board.result(), result(&gs), "{}", gs);
+            assert_eq!(board.utility(), utility(&gs), "{}", gs);
+            assert_eq!(board.weighted_potential_wins(&[0.0, 1.0, 10.0, 100.0]), weighted_potential_wins(&gs, &[0.0, 1.0, 10.0, 100.0]), "{}", gs);
+            assert_eq!(board.legal_cols().collect::<Vec<_>>(), get_legal(&gs).iter().map(|mov| mov.col()).collect::<Vec<_>>());
+            assert_eq!(board.children().count(), get_legal(&gs).len());
+            for ((mov, child), (col, child_board)) in children(&gs).zip(board.children()) {
+                assert_eq!(mov.col(), col);
+                assert_eq!(Some(child_board), BitBoard::from_game_state(&child));
+                assert_eq!(child_board.result(), result(&child), "{}", child);
+            }
+        }
+    }
+
+    #[test]
+    fn cached_result_follows_play() {
+        rng::seed(311);
+        for _ in 0..200 {
+            let mut gs = GameState::new();
+            let mut board = BitBoard::new();
+            loop {
+                assert_eq!(board.result(), result(&gs), "{}", gs);
+                assert_eq!(board.result(), board.find_result());
+                if result(&gs).is_some() {
+                    break;
+                }
+                let (mov, child) = children(&gs).nth(rng::with_rng(|rng| rng.next_u32()) as usize % get_legal(&gs).len()).unwrap();
+                board = board.play(mov.col()).unwrap();
+                gs = child;
+            }
+        }
+    }
+
+    /// Plays random games to the end under several seeds and compares every child of every
+    /// position along the way, finished ones included.
+    #[test]
+    fn children_agree_with_vec_board_to_the_end() {
+        let weights = [0.0, 1.0, 10.0, 100.0];
+        for seed in 314..319 {
+            rng::seed(seed);
+            for _ in 0..40 {
+                let mut gs = GameState::new();
+                let mut board = BitBoard::new();
+                while result(&gs).is_none() {
+                    for (mov, child) in children(&gs) {
+                        let child_board = board.play(mov.col()).unwrap();
+                        let context = format!("seed {} column {} from\n{}\nto\n{}", seed, mov.col(), gs, child);
+                        assert_eq!(Some(child_board), BitBoard::from_game_state(&child), "{}", context);
+                        assert_eq!(child_board.result(), result(&child), "{}", context);
+                        assert_eq!(child_board.utility(), utility(&child), "{}", context);
+                        assert_eq!(child_board.weighted_potential_wins(&weights), weighted_potential_wins(&child, &weights), "{}", context);
+                    }
+                    let moves = get_legal(&gs);
+                    let mov = moves[rng::with_rng(|rng| rng.next_u32()) as usize % moves.len()];
+                    board = board.play(mov.col()).unwrap();
+                    gs = play(mov, &gs).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_other_sizes() {
+        assert!(BitBoard::from_game_state(&GameState::new_with_size(7, 7)).is_none());
+        let mut gs = GameState::new();
+        gs.disable_column(0);
+        assert!(BitBoard::from_game_state(&gs).is_none());
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use crate::game::Agent;
+use crate::game_logic::{GameState, Move, MoveError, ParseError};
+
+/// Recommended columns for known positions. A position and its mirror share an entry, so a book
+/// only needs one side of each symmetric line.
+#[derive(Clone, Debug, Default)]
+pub struct OpeningBook {
+    moves: HashMap<GameState, usize>,
+}
+
+/// Whether `gs` is stored as its mirror, in which case its columns are stored mirrored too.
+fn is_flipped(gs: &GameState) -> bool {
+    gs.canonical() != *gs
+}
+
+impl OpeningBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads a book with one entry per line: a position in the compact notation followed by the
+    /// column to play, e.g. `7/7/7/7/7/7 x 3`. Blank lines and lines starting with `#` are skipped.
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut book = Self::new();
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let (position, col) = line.rsplit_once(' ')
+                .ok_or_else(|| ParseError(format!("missing column in '{}'", line)))?;
+            let gs = GameState::from_compact(position)?;
+            let bad_column = || ParseError(format!("bad column '{}' in '{}'", col, line));
+            let col = col.parse::<usize>().map_err(|_| bad_column())?;
+            book.insert(&gs, col).map_err(|_| bad_column())?;
+        }
+        Ok(book)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ParseError> {
+        let text = fs::read_to_string(path.as_ref())
+            .map_err(|error| ParseError(format!("cannot read {}: {}", path.as_ref().display(), error)))?;
+        Self::parse(&text)
+    }
+
+    /// The book in the format `parse` reads, one line per stored position.
+    pub fn to_text(&self) -> String {
+        let mut lines: Vec<String> = self.moves.iter()
+            .map(|(gs, col)| format!("{} {}\n", gs.to_string_compact(), col))
+            .collect();
+        lines.sort();
+        lines.concat()
+    }
+
+    /// Books `col` for `gs`, which must be a column of the board.
+    pub fn insert(&mut self, gs: &GameState, col: usize) -> Result<(), MoveError> {
+        if col >= gs.cols() {
+            return Err(MoveError::OffBoard);
+        }
+        let col = if is_flipped(gs) { gs.cols() - 1 - col } else { col };
+        self.moves.insert(gs.canonical(), col);
+        Ok(())
+    }
+
+    /// The booked move for `gs`, if there is one and it can be played.
+    pub fn lookup(&self, gs: &GameState) -> Option<Move> {
+        let col = *self.moves.get(&gs.canonical())?;
+        let col = if is_flipped(gs) { gs.cols().checked_sub(col + 1)? } else { col };
+        Move::drop(gs, col)
+    }
+
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+}
+
+/// Plays from an opening book while the game is in it, and leaves the rest to another agent.
+pub struct BookAgent {
+    book: OpeningBook,
+    fallback: Box<dyn Agent>,
+}
+
+impl BookAgent {
+    pub fn new(book: OpeningBook, fallback: Box<dyn Agent>) -> Self {
+        Self { book, fallback }
+    }
+}
+
+impl Agent for BookAgent {
+    fn label(&self) -> String {
+        format!("book({})", self.fallback.label())
+    }
+
+    fn next_move(&mut self, gs: &GameState) -> Move {
+        self.book.lookup(gs).unwrap_or_else(|| self.fallback.next_move(gs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::book::{BookAgent, OpeningBook};
+    use crate::game::Agent;
+    use crate::game_logic::{get_legal, play_col, GameState, Move, MoveError};
+
+    struct Leftmost;
+
+    impl Agent for Leftmost {
+        fn next_move(&mut self, gs: &GameState) -> Move {
+            get_legal(gs)[0]
+        }
+    }
+
+    #[test]
+    fn plays_booked_moves() {
+        let book = OpeningBook::parse("# after 1. 1\n7/7/7/7/7/1x5 o 2\n").unwrap();
+        assert_eq!(book.len(), 1);
+        let mut agent = BookAgent::new(book, Box::new(Leftmost));
+        let booked = play_col(1, &GameState::new()).unwrap();
+        assert_eq!(agent.next_move(&booked), Move::new(5, 2));
+        // The mirrored position gets the mirrored move.
+        let mirrored = play_col(5, &GameState::new()).unwrap();
+        assert_eq!(agent.next_move(&mirrored), Move::new(5, 4));
+        let elsewhere = play_col(3, &GameState::new()).unwrap();
+        assert_eq!(agent.next_move(&elsewhere), Move::new(5, 0));
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let mut book = OpeningBook::new();
+        book.insert(&GameState::new(), 3).unwrap();
+        book.insert(&play_col(6, &GameState::new()).unwrap(), 5).unwrap();
+        assert_eq!(book.insert(&play_col(6, &GameState::new()).unwrap(), 7), Err(MoveError::OffBoard));
+        assert_eq!(book.len(), 2);
+        let parsed = OpeningBook::parse(&book.to_text()).unwrap();
+        assert_eq!(parsed.to_text(), book.to_text());
+        assert_eq!(parsed.lookup(&play_col(0, &GameState::new()).unwrap()), Some(Move::new(5, 1)));
+        assert!(OpeningBook::parse("7/7/7/7/7/7 x 9").is_err());
+        assert!(OpeningBook::parse("7/7/7/7/7/7 x").is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::time::Duration;
+use crate::game::{Agent, Agents, MinMaxAgent, Score, DEFAULT_DEPTH};
+use crate::game_logic::{center_out, has_legal_move, result, GameState, Move, ParseError};
+
+/// A game set up from the command line. The interactive prompts are only skipped when both
+/// players are given.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameConfig {
+    pub rows: usize,
+    pub cols: usize,
+    pub p1: Option<Agents>,
+    pub p2: Option<Agents>,
+    /// The search depth of minmax players.
+    pub depth: Option<i32>,
+    /// The time limit per move of minmax players.
+    pub time: Option<Duration>,
+    pub seed: Option<u64>,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            rows: 6,
+            cols: 7,
+            p1: None,
+            p2: None,
+            depth: None,
+            time: None,
+            seed: None,
+        }
+    }
+}
+
+impl GameConfig {
+    pub fn is_interactive(&self) -> bool {
+        self.p1.is_none() || self.p2.is_none()
+    }
+
+    /// Checks that the flags can all be used for a game. The interactive prompts ask for the
+    /// players and their strength, so a single player, a depth or a time limit only go with both
+    /// players given.
+    pub fn check_game(&self) -> Result<(), ParseError> {
+        if self.is_interactive() && (self.p1.is_some() || self.p2.is_some() || self.depth.is_some() || self.time.is_some()) {
+            return Err(ParseError("--p1, --p2, --depth and --time need both --p1 and --p2".to_string()));
+        }
+        Ok(())
+    }
+
+    /// An agent of the kind given, with the configured depth and time limit for minmax.
+    pub fn agent(&self, kind: Agents) -> Box<dyn Agent> {
+        match kind {
+            Agents::MinMaxAgent => Box::new(MinMaxAgent::new_with_depth(self.depth.unwrap_or(DEFAULT_DEPTH)).with_time_limit(self.time)),
+            kind => <dyn Agent>::new(kind),
+        }
+    }
+}
+
+fn parse_agent(name: &str) -> Result<Agents, ParseError> {
+    match name {
+        "human" => Ok(Agents::Human),
+        "random" => Ok(Agents::RandomMover),
+        "minmax" => Ok(Agents::MinMaxAgent),
+        "mcts" => Ok(Agents::MctsAgent),
+        other => Err(ParseError(format!("unknown agent '{}', expected human, random, minmax or mcts", other))),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, ParseError> {
+    value.parse().map_err(|_| ParseError(format!("{} expects a number, got '{}'", flag, value)))
+}
+
+/// Reads `--rows`, `--cols`, `--p1`, `--p2`, `--depth`, `--time` (seconds per move) and `--seed`,
+/// each followed by its value. Flags left out keep their defaults.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<GameConfig, ParseError> {
+    let mut config = GameConfig::default();
+    let mut args = args.into_iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| ParseError(format!("{} expects a value", flag)))?;
+        match flag.as_str() {
+            "--rows" => config.rows = parse_number(&flag, &value)?,
+            "--cols" => config.cols = parse_number(&flag, &value)?,
+            "--p1" => config.p1 = Some(parse_agent(&value)?),
+            "--p2" => config.p2 = Some(parse_agent(&value)?),
+            "--depth" => match parse_number(&flag, &value)? {
+                depth if depth >= 1 => config.depth = Some(depth),
+                _ => return Err(ParseError(format!("--depth expects at least 1, got '{}'", value))),
+            },
+            "--time" => config.time = Some(Duration::try_from_secs_f64(parse_number(&flag, &value)?)
+                .map_err(|_| ParseError(format!("--time expects a number of seconds, got '{}'", value)))?),
+            "--seed" => config.seed = Some(parse_number(&flag, &value)?),
+            other => return Err(ParseError(format!("unknown flag '{}'", other))),
+        }
+    }
+    if config.rows == 0 || config.cols == 0 {
+        return Err(ParseError("the board needs at least one row and column".to_string()));
+    }
+    Ok(config)
+}
+
+/// Reads a position in compact notation (`7/7/7/7/7/3x3 o`) or as a board diagram.
+pub fn parse_board(text: &str) -> Result<GameState, ParseError> {
+    let text = text.trim();
+    if text.lines().count() == 1 && text.contains('/') {
+        GameState::from_compact(text)
+    } else {
+        GameState::from_ascii(text)
+    }
+}
+
+/// The score of every legal column and the move the engine recommends. `Display` writes one line
+/// per column and the recommendation last.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Analysis {
+    pub scores: Vec<(Move, Score)>,
+    pub best: Move,
+    pub best_score: Score,
+}
+
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (mov, score) in &self.scores {
+            writeln!(f, "column {}: {}", mov.col(), score)?;
+        }
+        write!(f, "recommended: column {} ({})", self.best.col(), self.best_score)
+    }
+}
+
+/// Analyzes the position in `text`, as `parse_board` reads it, with a `depth` ply search. The
+/// recommendation is the best scored column, the most central one on ties. Finished games are
+/// refused.
+pub fn analyze_board(text: &str, depth: i32) -> Result<Analysis, ParseError> {
+    let gs = parse_board(text)?;
+    if let Some(res) = result(&gs) {
+        return Err(ParseError(format!("the game is already over: {}", res.render(false))));
+    }
+    if !has_legal_move(&gs) {
+        return Err(ParseError("every open column is disabled".to_string()));
+    }
+    let scores = MinMaxAgent::new_with_depth(depth.max(1)).analyze_all(&gs);
+    let priority = center_out(gs.cols());
+    let mut ranked = scores.clone();
+    ranked.sort_by_key(|(mov, _)| priority.iter().position(|&col| col == mov.col()));
+    let (best, best_score) = ranked.into_iter()
+        .reduce(|best, next| if next.1.value() > best.1.value() { next } else { best })
+        .expect("an unfinished game has a legal move");
+    Ok(Analysis {
+        scores,
+        best,
+        best_score,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::cli::{analyze_board, parse_args, GameConfig};
+    use crate::game::Agents;
+    use crate::game_logic::Move;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn flags_fill_the_config() {
+        let config = parse_args(args("--rows 5 --cols 6 --p1 minmax --p2 mcts --depth 4 --time 0.5 --seed 9")).unwrap();
+        assert_eq!(config, GameConfig {
+            rows: 5,
+            cols: 6,
+            p1: Some(Agents::MinMaxAgent),
+            p2: Some(Agents::MctsAgent),
+            depth: Some(4),
+            time: Some(Duration::from_millis(500)),
+            seed: Some(9),
+        });
+        assert!(!config.is_interactive());
+        assert_eq!(config.agent(Agents::MinMaxAgent).label(), "minmax-d4-window-t500ms");
+        let defaults = parse_args(args("")).unwrap();
+        assert_eq!(defaults, GameConfig::default());
+        assert!(defaults.is_interactive());
+        assert!(parse_args(args("--p1 random")).unwrap().is_interactive());
+        assert!(parse_args(args("--rows")).is_err());
+        assert!(parse_args(args("--rows six")).is_err());
+        assert!(parse_args(args("--p1 alphazero")).is_err());
+        assert!(parse_args(args("--colour red")).is_err());
+        assert!(parse_args(args("--cols 0")).is_err());
+        for bad in ["--depth 0", "--depth -3", "--time -1", "--time nan", "--time 1e300"] {
+            assert!(parse_args(args(bad)).is_err(), "{}", bad);
+        }
+        assert!(config.check_game().is_ok());
+        assert!(defaults.check_game().is_ok());
+        for partial in ["--p1 random", "--p2 minmax", "--depth 3", "--time 1"] {
+            assert!(parse_args(args(partial)).unwrap().check_game().is_err(), "{}", partial);
+        }
+    }
+
+    #[test]
+    fn analysis_recommends_the_win() {
+        let analysis = analyze_board("7/7/7/2o4/2o4/2oxxx1 x", 4).unwrap();
+        assert_eq!(analysis.best, Move::new(5, 6));
+        let text = analysis.to_string();
+        assert_eq!(text.lines().count(), 8);
+        assert!(text.contains("column 6: mate in 1\n"), "{}", text);
+        assert!(text.ends_with("recommended: column 6 (mate in 1)"), "{}", text);
+        let diagram = "
+            .......
+            .......
+            .......
+            ..O....
+            ..O....
+            ..OXXX.
+        ";
+        assert_eq!(analyze_board(diagram, 4).unwrap().best, Move::new(5, 6));
+        assert!(analyze_board("7/7 z", 4).is_err());
+        assert!(analyze_board("xox/oxo/xox o", 4).is_err());
+        assert!(analyze_board("7/7/7/o6/o6/oxxxx2 o", 4).is_err());
+        for depth in [-1, 0, 1, 2] {
+            let analysis = analyze_board("7/7/7/7/7/7 x", depth).unwrap();
+            let top = analysis.scores.iter().map(|(_, score)| score.value()).fold(f32::NEG_INFINITY, f32::max);
+            assert_eq!(analysis.best_score.value(), top, "depth {}", depth);
+            assert!(analysis.scores.contains(&(analysis.best, analysis.best_score)));
+        }
+    }
+}
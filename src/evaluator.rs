@@ -0,0 +1,60 @@
+use crate::game_logic::{center_bonus, potential_wins, weighted_potential_wins, GameState};
+
+/// A static evaluation of an undecided position from P1's perspective, for the search to call
+/// at its leaves. Won and drawn positions are scored by the search itself.
+///
+/// Evaluators see the whole position on every call. One that could be updated incrementally
+/// still has to produce the same value from scratch, since nothing tells it which move was made.
+pub trait Evaluator: Send + Sync {
+    fn evaluate(&self, gs: &GameState) -> f32;
+
+    /// A short name for agent labels, which tells apart evaluators set up differently.
+    fn label(&self) -> String {
+        "custom".to_string()
+    }
+}
+
+/// The default heuristic: how many more lines P1 could still complete than P2, with the center
+/// bonus to tell otherwise equal positions apart. The same value as `utility` gives.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WindowEvaluator;
+
+impl Evaluator for WindowEvaluator {
+    fn evaluate(&self, gs: &GameState) -> f32 {
+        let (p1, p2) = potential_wins(gs);
+        (p1 - p2) as f32 + center_bonus(gs)
+    }
+
+    fn label(&self) -> String {
+        "window".to_string()
+    }
+}
+
+/// Like `WindowEvaluator`, but an open line is worth more the more of the player's discs are
+/// already in it, as `weighted_potential_wins` counts it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WeightedWindowEvaluator {
+    /// The worth of an open line holding 0, 1, 2, ... of the player's discs.
+    pub weights: Vec<f32>,
+}
+
+impl Default for WeightedWindowEvaluator {
+    /// Empty lines are open to both players alike, so they are left out.
+    fn default() -> Self {
+        Self {
+            weights: vec![0.0, 1.0, 10.0, 100.0],
+        }
+    }
+}
+
+impl Evaluator for WeightedWindowEvaluator {
+    fn evaluate(&self, gs: &GameState) -> f32 {
+        let (p1, p2) = weighted_potential_wins(gs, &self.weights);
+        p1 - p2 + center_bonus(gs)
+    }
+
+    fn label(&self) -> String {
+        let weights: Vec<String> = self.weights.iter().map(f32::to_string).collect();
+        format!("weighted({})", weights.join(","))
+    }
+}
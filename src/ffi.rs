@@ -0,0 +1,153 @@
+//! A C interface to the engine. Boards live behind opaque `C4Board` handles that the caller owns:
+//! every handle from `c4_new_board` must be released with `c4_free` exactly once, and must not be
+//! used afterwards. Handles are not thread-safe.
+//!
+//! A panic must not unwind into the caller, so the functions that do real work catch it and
+//! report an error instead. The shared library is built with
+//! `cargo rustc --lib --release --features ffi --crate-type cdylib`.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use crate::game::{Agent, MinMaxAgent};
+use crate::game_logic::{play_col, result, GameResult, GameState, Player};
+
+/// An opaque board handle.
+pub struct C4Board {
+    gs: GameState,
+}
+
+pub const C4_ONGOING: i32 = -1;
+pub const C4_DRAW: i32 = 0;
+pub const C4_P1_WINS: i32 = 1;
+pub const C4_P2_WINS: i32 = 2;
+
+/// Creates a board from `rows * cols` cells in row-major order from the top row down, each 0 for
+/// empty, 1 for P1 or 2 for P2. The side to move follows from the disc counts. Returns null for
+/// a board that cannot come up in a game or is too large to create.
+///
+/// # Safety
+///
+/// `cells` must point to `rows * cols` readable `i8`s.
+#[no_mangle]
+pub unsafe extern "C" fn c4_new_board(cells: *const i8, rows: usize, cols: usize) -> *mut C4Board {
+    let len = match rows.checked_mul(cols) {
+        Some(len) if !cells.is_null() && len > 0 && len <= isize::MAX as usize => len,
+        _ => return ptr::null_mut(),
+    };
+    let cells = slice::from_raw_parts(cells, len);
+    panic::catch_unwind(|| match GameState::try_from_board(cells.chunks(cols).map(|row| row.to_vec()).collect()) {
+        Ok(gs) => Box::into_raw(Box::new(C4Board { gs })),
+        Err(_) => ptr::null_mut(),
+    }).unwrap_or(ptr::null_mut())
+}
+
+/// Releases a handle from `c4_new_board`. Null is ignored.
+///
+/// # Safety
+///
+/// `board` must be null or a live handle, which is invalid afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn c4_free(board: *mut C4Board) {
+    if !board.is_null() {
+        drop(Box::from_raw(board));
+    }
+}
+
+/// Drops a disc in `col` for the side to move. Returns 0, or -1 if the move is illegal or the
+/// game is already over, in which case the board is unchanged.
+///
+/// # Safety
+///
+/// `board` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn c4_play_column(board: *mut C4Board, col: usize) -> i32 {
+    let board = &mut *board;
+    if result(&board.gs).is_some() {
+        return -1;
+    }
+    match play_col(col, &board.gs) {
+        Some(gs) => {
+            board.gs = gs;
+            0
+        }
+        None => -1,
+    }
+}
+
+/// One of `C4_ONGOING`, `C4_DRAW`, `C4_P1_WINS` and `C4_P2_WINS`.
+///
+/// # Safety
+///
+/// `board` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn c4_result(board: *const C4Board) -> i32 {
+    match result(&(*board).gs) {
+        None => C4_ONGOING,
+        Some(GameResult::Draw) => C4_DRAW,
+        Some(GameResult::Win(Player::P1)) => C4_P1_WINS,
+        Some(GameResult::Win(Player::P2)) => C4_P2_WINS,
+    }
+}
+
+/// The column a minimax search `depth` plies deep plays for the side to move, or -1 if the game
+/// is over or the search fails.
+///
+/// # Safety
+///
+/// `board` must be a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn c4_best_move(board: *const C4Board, depth: i32) -> i32 {
+    let gs = &(*board).gs;
+    if result(gs).is_some() {
+        return -1;
+    }
+    panic::catch_unwind(AssertUnwindSafe(|| MinMaxAgent::new_with_depth(depth.max(1)).next_move(gs).col() as i32)).unwrap_or(-1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+    use crate::ffi::{c4_best_move, c4_free, c4_new_board, c4_play_column, c4_result, C4Board, C4_ONGOING, C4_P1_WINS};
+
+    #[test]
+    fn finds_the_winning_column() {
+        let cells: [i8; 42] = [
+            0,0,0,0,0,0,0,
+            0,0,0,0,0,0,0,
+            0,0,0,0,0,0,0,
+            0,0,0,0,0,0,0,
+            0,2,2,0,0,0,0,
+            0,1,1,1,0,2,0,
+        ];
+        let new_board: unsafe extern "C" fn(*const i8, usize, usize) -> *mut C4Board = c4_new_board;
+        unsafe {
+            let board = new_board(cells.as_ptr(), 6, 7);
+            assert!(!board.is_null());
+            assert_eq!(c4_result(board), C4_ONGOING);
+            let col = c4_best_move(board, 4);
+            assert!(col == 0 || col == 4, "{}", col);
+            assert_eq!(c4_play_column(board, col as usize), 0);
+            assert_eq!(c4_result(board), C4_P1_WINS);
+            assert_eq!(c4_play_column(board, 6), -1);
+            assert_eq!(c4_best_move(board, 4), -1);
+            c4_free(board);
+            c4_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn rejects_impossible_boards() {
+        let floating: [i8; 4] = [1, 0, 0, 0];
+        unsafe {
+            assert!(c4_new_board(floating.as_ptr(), 2, 2).is_null());
+            assert!(c4_new_board(ptr::null(), 2, 2).is_null());
+            assert!(c4_new_board(floating.as_ptr(), usize::MAX, 2).is_null());
+            assert!(c4_new_board(floating.as_ptr(), 1 << 62, 4).is_null());
+            let board = c4_new_board([0i8; 4].as_ptr(), 2, 2);
+            assert_eq!(c4_play_column(board, 2), -1);
+            assert_eq!(c4_play_column(board, 1), 0);
+            c4_free(board);
+        }
+    }
+}
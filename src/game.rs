@@ -1,93 +1,572 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
 use std::io;
-use crate::game_logic::{play, GameState, Move, get_legal, Player, result, GameResult, eval};
+use std::sync::{mpsc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::game_logic::{BoardError, center_out, children, classify_moves, MoveClass, colors_enabled, next_turn, play, play_col, try_play, GameState, Move, MoveError, get_legal, has_legal_move, legal_moves, Player, result, GameResult, is_symmetrical, creates_fork, disc_count, make_move, unmake_move, winning_moves, eval_move, EvalParams};
 use rand::prelude::*;
-use rulinalg::utils;
-use rulinalg::utils::argmax;
+use rand_chacha::ChaCha8Rng;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use crate::mcts::{MctsAgent, DEFAULT_ITERATIONS};
+use crate::rng::with_rng;
+use crate::transposition::{Bound, TranspositionTable};
+use crate::evaluator::{Evaluator, WindowEvaluator};
 
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
-pub struct Game {
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ReplayError {
+    NotAColumn(String),
+    /// The column at this ply, counting from 0, is off the board or full.
+    Illegal { ply: usize, col: usize },
+    /// There are moves left after the game ended at this ply.
+    GameOver { ply: usize },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::NotAColumn(token) => write!(f, "'{}' is not a column number", token),
+            ReplayError::Illegal { ply, col } => write!(f, "column {} cannot be played at ply {}", col, ply),
+            ReplayError::GameOver { ply } => write!(f, "the game is already over at ply {}", ply),
+        }
+    }
+}
+
+/// Who moves first in a new game.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FirstPlayer {
+    Fixed(Player),
+    /// Either player with equal chance, drawn from the shared `rng`.
+    Random,
+}
+
+/// What happens to an agent that takes longer than the move time limit.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimeoutPolicy {
+    /// The agent loses the game.
+    Forfeit,
+    /// A random legal move is played instead of the agent's.
+    RandomMove,
+}
+
+/// An agent's place in a session. An agent that runs out of time is left thinking on its own
+/// thread, and the seat stays empty until it comes back.
+struct Seat {
+    agent: Option<Box<dyn Agent>>,
+    late: Option<mpsc::Receiver<Box<dyn Agent>>>,
+}
+
+impl Seat {
+    fn new(agent: Box<dyn Agent>) -> Self {
+        Self { agent: Some(agent), late: None }
+    }
+
+    /// The agent, if it is back from any move it ran out of time for. With `wait` set this waits
+    /// for it to come back.
+    fn agent(&mut self, wait: bool) -> Option<&mut Box<dyn Agent>> {
+        if let Some(late) = &self.late {
+            let back = if wait { late.recv().ok() } else { late.try_recv().ok() };
+            if let Some(agent) = back {
+                self.agent = Some(agent);
+                self.late = None;
+            }
+        }
+        self.agent.as_mut()
+    }
+
+    /// Puts `ask` to the agent, on a thread of its own when there is a `limit`, and returns the
+    /// answer unless the agent is still away or takes longer than `limit`. The thread draws from a
+    /// generator seeded from the shared `rng`, so seeded games stay reproducible.
+    fn ask<T: Send + 'static>(&mut self, gs: &GameState, limit: Option<Duration>, ask: fn(&mut dyn Agent, &GameState) -> T) -> Option<T> {
+        let Some(limit) = limit else {
+            return self.agent(true).map(|agent| ask(agent.as_mut(), gs));
+        };
+        self.agent(false)?;
+        let mut agent = self.agent.take()?;
+        let (answers, answer) = mpsc::channel();
+        let (agents, back) = mpsc::channel();
+        let gs = gs.clone();
+        let seed = with_rng(|rng| rng.next_u64());
+        thread::spawn(move || {
+            crate::rng::seed(seed);
+            let _ = answers.send(ask(agent.as_mut(), &gs));
+            let _ = agents.send(agent);
+        });
+        match answer.recv_timeout(limit) {
+            Ok(answer) => {
+                self.agent = back.recv().ok();
+                Some(answer)
+            }
+            Err(_) => {
+                self.late = Some(back);
+                None
+            }
+        }
+    }
+}
+
+/// A game between two agents that is driven from outside, without any terminal I/O, so that it
+/// can sit behind a GUI or a browser front-end. Human moves come in through `apply_column`, and
+/// `agent_move` lets the agent on turn move.
+pub struct GameSession {
     gs: GameState,
-    player_1: Box<dyn Agent>,
-    player_2: Box<dyn Agent>,
+    player_1: Seat,
+    player_2: Seat,
+    /// Set once the game is over, which a resignation or an agreed draw can make it before the
+    /// board shows a result.
+    result: Option<GameResult>,
+    history: Vec<Move>,
+    /// Moves taken back with `undo`, most recent last, until a new move is played.
+    undone: Vec<Move>,
+    move_time_limit: Option<(Duration, TimeoutPolicy)>,
+}
+
+impl GameSession {
+    pub fn new(rows : usize, cols : usize, player_1 : Box<dyn Agent>, player_2 : Box<dyn Agent>) -> Self {
+        Self {
+            gs : GameState::new_with_size(rows, cols),
+            player_1 : Seat::new(player_1),
+            player_2 : Seat::new(player_2),
+            result : None,
+            history : Vec::new(),
+            undone : Vec::new(),
+            move_time_limit : None,
+        }
+    }
+
+    /// A session that goes on from `gs` rather than an empty board, with whoever is to move there
+    /// moving first. Positions that `GameState::validate` rejects are refused, and so are Pop Out
+    /// positions, since agents only drop discs.
+    pub fn from_position(gs : GameState, player_1 : Box<dyn Agent>, player_2 : Box<dyn Agent>) -> Result<Self, BoardError> {
+        gs.validate()?;
+        if gs.pop_out() {
+            return Err(BoardError::PopOut);
+        }
+        Ok(Self {
+            gs,
+            player_1 : Seat::new(player_1),
+            player_2 : Seat::new(player_2),
+            result : None,
+            history : Vec::new(),
+            undone : Vec::new(),
+            move_time_limit : None,
+        })
+    }
+
+    /// Holds agents to `limit` per move, applying `policy` to one that takes longer. Agents think
+    /// on a thread of their own then, so one that hangs cannot hang the session, and until it
+    /// comes back it runs out of time on every turn.
+    pub fn set_move_time_limit(&mut self, limit : Option<Duration>, policy : TimeoutPolicy) {
+        self.move_time_limit = limit.map(|limit| (limit, policy));
+    }
+
+    /// Hands the first move to `player` on an empty board. Once there are discs on it, played or
+    /// set up with `from_position`, it is too late and `false` is returned.
+    pub fn set_first_player(&mut self, player : Player) -> bool {
+        if disc_count(&self.gs) > 0 {
+            return false;
+        }
+        self.gs.turn = player;
+        true
+    }
+
+    pub fn current_state(&self) -> &GameState {
+        &self.gs
+    }
+
+    pub fn history(&self) -> &[Move] {
+        &self.history
+    }
+
+    pub fn result(&self) -> Option<GameResult> {
+        self.result
+    }
+
+    /// Plays `mov` for the side to move, returning the result if that ends the game.
+    pub fn play(&mut self, mov: Move) -> Result<Option<GameResult>, MoveError> {
+        if self.result.is_some() {
+            return Err(MoveError::GameOver);
+        }
+        self.apply(mov)
+    }
+
+    /// Drops a disc in `col` for the side to move, returning the result if that ends the game.
+    pub fn apply_column(&mut self, col: usize) -> Result<Option<GameResult>, MoveError> {
+        if self.result.is_some() {
+            return Err(MoveError::GameOver);
+        }
+        if col >= self.gs.lanes() {
+            return Err(MoveError::OffBoard);
+        }
+        let mov = Move::drop(&self.gs, col)
+            .ok_or_else(|| if self.gs.is_column_disabled(col) { MoveError::ColumnDisabled } else { MoveError::Occupied })?;
+        self.apply(mov)
+    }
+
+    /// Plays `mov` for the side to move, returning the result if that ends the game.
+    fn apply(&mut self, mov: Move) -> Result<Option<GameResult>, MoveError> {
+        self.gs = try_play(mov, &self.gs)?;
+        self.history.push(mov);
+        self.undone.clear();
+        self.result = result(&self.gs);
+        Ok(self.result)
+    }
+
+    /// Lets the agent on turn act, returning the result if that ends the game. The move it played
+    /// is the last one in `history`. The agent isn't asked when it has no legal move.
+    pub fn agent_move(&mut self) -> Result<Option<GameResult>, MoveError> {
+        if self.result.is_some() {
+            return Err(MoveError::GameOver);
+        }
+        if !has_legal_move(&self.gs) {
+            return Err(MoveError::NoMoves);
+        }
+        let (mover, opponent) = match self.gs.turn{
+            Player::P1 => (&mut self.player_1, &mut self.player_2),
+            Player::P2 => (&mut self.player_2, &mut self.player_1),
+        };
+        let (limit, policy) = match self.move_time_limit {
+            Some((limit, policy)) => (Some(limit), policy),
+            // Only an agent that died on its thread can fail to answer without a limit.
+            None => (None, TimeoutPolicy::Forfeit),
+        };
+        let started = Instant::now();
+        let gs = &self.gs;
+        let turn = match mover.ask(gs, limit, |agent, gs| agent.propose(gs)) {
+            Some(Action::Move(mov)) => Some(Ok(mov)),
+            Some(Action::Resign) => Some(Err(GameResult::Win(next_turn(gs.turn)))),
+            Some(Action::OfferDraw) if opponent.agent(limit.is_none()).is_some_and(|agent| agent.accept_draw(gs)) => Some(Err(GameResult::Draw)),
+            Some(Action::OfferDraw) => {
+                let left = limit.map(|limit| limit.saturating_sub(started.elapsed()));
+                mover.ask(gs, left, |agent, gs| agent.next_move(gs)).map(Ok)
+            }
+            None => None,
+        };
+        let turn = turn.unwrap_or_else(|| match policy {
+            TimeoutPolicy::Forfeit => Err(GameResult::Win(next_turn(gs.turn))),
+            TimeoutPolicy::RandomMove => {
+                let moves = get_legal(gs);
+                Ok(moves[with_rng(|rng| rng.gen_range(0..moves.len()))])
+            }
+        });
+        match turn {
+            Ok(mov) => self.apply(mov),
+            Err(res) => {
+                self.result = Some(res);
+                Ok(self.result)
+            }
+        }
+    }
+
+    /// Takes back the last move, handing the turn back to the player who made it.
+    pub fn undo(&mut self) -> Option<Move> {
+        let mov = self.history.pop()?;
+        unmake_move(mov, &mut self.gs);
+        self.undone.push(mov);
+        self.result = None;
+        Some(mov)
+    }
+
+    /// Replays the last move taken back with `undo`.
+    pub fn redo(&mut self) -> Option<Move> {
+        let mov = self.undone.pop()?;
+        make_move(mov, &mut self.gs);
+        self.history.push(mov);
+        self.result = result(&self.gs);
+        Some(mov)
+    }
+}
+
+/// Something that happened in `Game::start_game`, for a front-end to react to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameEvent<'a> {
+    /// `mov` was played, leading to `state`.
+    MoveMade { state: &'a GameState, mov: Move },
+    GameOver(GameResult),
+}
+
+/// Told about every `GameEvent` as the game goes.
+pub type GameObserver = Box<dyn FnMut(&GameEvent)>;
+
+/// A `GameSession` played out on the terminal.
+pub struct Game {
+    session: GameSession,
+    move_delay: Option<Duration>,
+    render: bool,
+    colored: bool,
+    observer: Option<GameObserver>,
 }
 
 impl Game {
 
-    fn generate_agent(player : Player) -> Box<dyn Agent> {
+    fn select_agent_type(player : Player) -> Agents {
         println!("Please select agent type for {:}", player);
         let agent_types : Vec<Agents> = Agents::iter().collect();
         Game::display_agent_options(&agent_types);
         let index = get_int_in_range_from_user(0, agent_types.len());
-        let agent = agent_types[index];
-        <dyn Agent>::new(agent)
+        agent_types[index]
+    }
+
+    fn select_difficulty() -> Difficulty {
+        println!("Please select a difficulty");
+        let difficulties : Vec<Difficulty> = Difficulty::iter().collect();
+        for (i, difficulty) in difficulties.iter().enumerate() {
+            println!("{:}: {:?}", i, difficulty)
+        }
+        difficulties[get_int_in_range_from_user(0, difficulties.len())]
+    }
+
+    fn select_agent(agent_type : Agents, rows : usize, cols : usize) -> Box<dyn Agent> {
+        match agent_type {
+            Agents::MinMaxAgent => Box::new(MinMaxAgent::from_difficulty(Game::select_difficulty(), rows, cols)),
+            other => <dyn Agent>::new(other),
+        }
     }
 
-    fn display_agent_options(options : &Vec<Agents>){
-        for (i, option) in options.into_iter().enumerate(){
+    fn select_move_delay() -> Option<Duration> {
+        println!("Please select a delay between moves in milliseconds (0 for none)");
+        match get_int_in_range_from_user(0, 60_000) {
+            0 => None,
+            millis => Some(Duration::from_millis(millis as u64)),
+        }
+    }
+
+    fn display_agent_options(options : &[Agents]){
+        for (i, option) in options.iter().enumerate(){
             println!("{:}: {:?}", i, option)
         }
     }
 
-    pub fn new() -> Self {
+    /// Asks on the terminal which agents should play, and renders the game as it goes.
+    pub fn new_interactive(rows : usize, cols : usize) -> Self {
+        let agent_types = [Game::select_agent_type(Player::P1), Game::select_agent_type(Player::P2)];
+        let move_delay = if agent_types.contains(&Agents::Human) { None } else { Game::select_move_delay() };
+        let player_1 = Game::select_agent(agent_types[0], rows, cols);
+        let player_2 = Game::select_agent(agent_types[1], rows, cols);
+        let mut game = Self::new_with_agents(rows, cols, player_1, player_2);
+        game.set_move_delay(move_delay);
+        game.set_render(true);
+        game
+    }
+
+    /// A headless game between the given agents, without any prompting.
+    pub fn new_with_agents(rows : usize, cols : usize, player_1 : Box<dyn Agent>, player_2 : Box<dyn Agent>) -> Self {
         Self {
-            gs : GameState::new(),
-            player_1 : Game::generate_agent(Player::P1),
-            player_2 : Game::generate_agent(Player::P2),
+            session : GameSession::new(rows, cols, player_1, player_2),
+            move_delay : None,
+            render : false,
+            colored : colors_enabled(),
+            observer : None,
         }
     }
 
-    fn play(&mut self, mov: Move) -> bool {
-        return match play(mov, &self.gs){
-            None => false,
-            Some(gs) => {
-                self.gs = gs;
-                true
-            }
+    /// A headless game that resumes from `gs`, such as a saved game or a puzzle. The agents start
+    /// with whoever is to move in `gs`.
+    pub fn play_from_position(gs : GameState, player_1 : Box<dyn Agent>, player_2 : Box<dyn Agent>) -> Result<Self, BoardError> {
+        Ok(Self {
+            session : GameSession::from_position(gs, player_1, player_2)?,
+            move_delay : None,
+            render : false,
+            colored : colors_enabled(),
+            observer : None,
+        })
+    }
+
+    /// Holds the agents to `limit` per move, as `GameSession::set_move_time_limit` does.
+    pub fn set_move_time_limit(&mut self, limit: Option<Duration>, policy: TimeoutPolicy) {
+        self.session.set_move_time_limit(limit, policy);
+    }
+
+    /// Picks who moves first, which only works on an empty board. Returns the player picked, or
+    /// `None` when the game is already under way.
+    pub fn set_first_player(&mut self, first: FirstPlayer) -> Option<Player> {
+        let player = match first {
+            FirstPlayer::Fixed(player) => player,
+            FirstPlayer::Random => if with_rng(|rng| rng.gen_bool(0.5)) { Player::P1 } else { Player::P2 },
+        };
+        self.session.set_first_player(player).then_some(player)
+    }
+
+    /// Pause between plies so bot-vs-bot games can be followed on screen.
+    pub fn set_move_delay(&mut self, move_delay: Option<Duration>) {
+        self.move_delay = move_delay;
+    }
+
+    /// Colored output can be turned off for terminals and logs that do not understand ANSI codes.
+    /// It starts off disabled when `NO_COLOR` is set.
+    pub fn set_colored(&mut self, colored: bool) {
+        self.colored = colored;
+    }
+
+    /// Disable to run the game headless without printing any positions.
+    pub fn set_render(&mut self, render: bool) {
+        self.render = render;
+    }
+
+    /// Calls `observer` after every move of `start_game` and once more when the game ends.
+    pub fn set_observer(&mut self, observer: GameObserver) {
+        self.observer = Some(observer);
+    }
+
+    pub fn play(&mut self, mov: Move) -> Result<Option<GameResult>, MoveError> {
+        self.session.play(mov)
+    }
+
+    pub fn state(&self) -> &GameState {
+        self.session.current_state()
+    }
+
+    pub fn history(&self) -> &[Move] {
+        self.session.history()
+    }
+
+    /// The columns played so far separated by spaces, e.g. `3 3 4 2`, which `replay` reads back.
+    /// Under sideways gravity these are the rows the discs were slid into. A game that P2 started
+    /// is marked with a leading `o`, the side to move of the compact notation, e.g. `o 3 3 4 2`.
+    pub fn export_moves(&self) -> String {
+        let turn = self.state().turn();
+        let first = if self.history().len().is_multiple_of(2) { turn } else { next_turn(turn) };
+        let lanes = self.history().iter().map(|mov| self.state().lane_of(*mov).to_string());
+        let tokens: Vec<String> = (first == Player::P2).then(|| "o".to_string()).into_iter().chain(lanes).collect();
+        tokens.join(" ")
+    }
+
+    /// Plays the moves of `export_moves` on an empty `rows` x `cols` board, starting with P2 when
+    /// they begin with `o` and with P1 otherwise.
+    pub fn replay(moves: &str, rows: usize, cols: usize) -> Result<GameState, ReplayError> {
+        let mut tokens = moves.split_whitespace().peekable();
+        let first = match tokens.next_if(|token| *token == "x" || *token == "o") {
+            Some("o") => Player::P2,
+            _ => Player::P1,
         };
+        let mut gs = GameState::new_with_turn(rows, cols, first);
+        for (ply, token) in tokens.enumerate() {
+            let col = token.parse().map_err(|_| ReplayError::NotAColumn(token.to_string()))?;
+            if result(&gs).is_some() {
+                return Err(ReplayError::GameOver { ply });
+            }
+            gs = play_col(col, &gs).ok_or(ReplayError::Illegal { ply, col })?;
+        }
+        Ok(gs)
+    }
+
+    /// Takes back the last move, handing the turn back to the player who made it.
+    pub fn undo(&mut self) -> Option<Move> {
+        self.session.undo()
+    }
+
+    /// Replays the last move taken back with `undo`.
+    pub fn redo(&mut self) -> Option<Move> {
+        self.session.redo()
     }
 
+    /// An agent that plays an illegal move forfeits the game. Nothing enables columns again during
+    /// a game, so one where every open column is disabled ends in a draw.
     fn next(&mut self) -> Option<GameResult>{
-        let mov = match self.gs.turn{
-            Player::P1 => {&self.player_1}
-            Player::P2 => {&self.player_2}
-        }.next_move(& self.gs);
-        self.play(mov);
-        return result(&self.gs);
+        let res = match self.session.agent_move() {
+            Ok(res) => return res,
+            Err(MoveError::GameOver) => return self.session.result(),
+            Err(MoveError::NoMoves) => GameResult::Draw,
+            Err(_) => GameResult::Win(next_turn(self.state().turn())),
+        };
+        self.session.result = Some(res);
+        Some(res)
     }
 
-    fn game_over(&self, res : GameResult){
-        println!("{:}", self.gs);
-        println!("The game ended with the following result: {:}", res)
+    fn notify(&mut self, event: &GameEvent) {
+        if self.render {
+            match event {
+                GameEvent::MoveMade { state, .. } => println!("{:}", state.render(self.colored)),
+                GameEvent::GameOver(res) => println!("The game ended with the following result: {:}", res.render(self.colored)),
+            }
+        }
+        if let Some(observer) = self.observer.as_mut() {
+            observer(event);
+        }
     }
 
-    fn game_loop(&mut self){
-        println!("{:}", self.gs);
-        let res = self.next();
-        match res {
-            None => { self.game_loop(); }
-            Some(r) => {self.game_over(r)}
+    fn game_loop(&mut self) -> GameResult {
+        if self.render {
+            println!("{:}", self.state().render(self.colored));
+        }
+        loop {
+            let played = self.history().len();
+            let res = self.next();
+            if self.history().len() > played {
+                let state = self.session.current_state().clone();
+                let mov = *self.history().last().unwrap();
+                self.notify(&GameEvent::MoveMade { state: &state, mov });
+            }
+            if let Some(res) = res {
+                self.notify(&GameEvent::GameOver(res));
+                return res;
+            }
+            if let Some(delay) = self.move_delay {
+                thread::sleep(delay);
+            }
         }
     }
 
-    pub fn start_game(&mut self){
-        self.game_loop();
+    pub fn start_game(&mut self) -> GameResult {
+        self.game_loop()
     }
 }
 
-pub trait Agent {
-    fn next_move(&self, gs: &GameState) -> Move;
+/// What an agent does on its turn.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    Move(Move),
+    Resign,
+    /// Offers a draw, and has to move after all if the opponent declines.
+    OfferDraw,
+}
+
+/// A player. Agents are `Send` so that a session can let them think on a thread of their own,
+/// see `GameSession::set_move_time_limit`.
+pub trait Agent: Send {
+    fn next_move(&mut self, gs: &GameState) -> Move;
+
+    /// Agents that know when a game is decided can resign or offer a draw instead of moving.
+    fn propose(&mut self, gs: &GameState) -> Action {
+        Action::Move(self.next_move(gs))
+    }
+
+    fn accept_draw(&mut self, _gs: &GameState) -> bool {
+        false
+    }
+
+    /// A name for tournaments and ratings that tells apart differently configured agents.
+    fn label(&self) -> String {
+        "agent".to_string()
+    }
+}
+
+/// Asks `mover` for its action, returning the move to play, or the result if the game ends by
+/// resignation or agreement instead.
+pub(crate) fn take_turn(gs: &GameState, mover: &mut dyn Agent, opponent: &mut dyn Agent) -> Result<Move, GameResult> {
+    match mover.propose(gs) {
+        Action::Move(mov) => Ok(mov),
+        Action::Resign => Err(GameResult::Win(next_turn(gs.turn))),
+        Action::OfferDraw if opponent.accept_draw(gs) => Err(GameResult::Draw),
+        Action::OfferDraw => Ok(mover.next_move(gs)),
+    }
 }
 
 impl dyn Agent {
     pub fn new(agent_type : Agents) -> Box<dyn Agent> {
-        let agent : Box<dyn Agent> = (match agent_type {
+        match agent_type {
             Agents::Human => Box::new(Human::new()),
             Agents::RandomMover => Box::new(RandomMover::new()),
             Agents::MinMaxAgent => Box::new(MinMaxAgent::new()),
-        });
-        agent
+            Agents::MctsAgent => Box::new(MctsAgent::new(DEFAULT_ITERATIONS)),
+        }
     }
 }
 
@@ -96,10 +575,17 @@ pub enum Agents {
     Human,
     RandomMover,
     MinMaxAgent,
+    MctsAgent,
 }
 
 pub struct Human {}
 
+impl Default for Human {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Human {
     pub fn new() -> Self {
         Self {
@@ -107,11 +593,25 @@ impl Human {
     }
 }
 impl Agent for Human {
-    fn next_move(&self, gs: &GameState) -> Move {
-        let moves = get_legal(&gs);
-        println!("{:?}", moves);
-        println!("{:} to move. Select a move from the list", gs.turn);
-        moves[get_int_in_range_from_user(0, moves.len())]
+    fn label(&self) -> String {
+        "human".to_string()
+    }
+
+    fn next_move(&mut self, gs: &GameState) -> Move {
+        let lanes : Vec<usize> = get_legal(gs).iter().map(|mov| gs.lane_of(*mov)).collect();
+        println!("{:?}", lanes);
+        println!("{:} to move. Select a column from the list", gs.turn);
+        loop {
+            let lane = get_int_in_range_from_user(0, gs.lanes());
+            if let Some(mov) = Move::from_col(lane, gs) {
+                return mov;
+            }
+            if gs.is_column_disabled(lane) {
+                println!("Illegal move: {}", MoveError::ColumnDisabled);
+            } else {
+                println!("That column is full!");
+            }
+        }
     }
 }
 
@@ -123,12 +623,9 @@ fn get_int_in_range_from_user(lower : usize, upper : usize)-> usize{
         let mut input_line = String::new();
         let res = io::stdin()
             .read_line(&mut input_line);
-        match res{
-            Err(_) => {
-                print_illegal();
-                continue
-            },
-            _ => {}
+        if res.is_err() {
+            print_illegal();
+            continue
         }
         let index_res : Result<usize, _> = input_line.trim().parse();
         match index_res {
@@ -142,35 +639,1935 @@ fn get_int_in_range_from_user(lower : usize, upper : usize)-> usize{
 
 }
 
-pub struct RandomMover {}
+pub struct RandomMover {
+    /// Its own generator when seeded, otherwise the shared one from `rng`.
+    rng: Option<ChaCha8Rng>,
+}
+
+impl Default for RandomMover {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl RandomMover {
     pub fn new() -> Self {
         Self {
+            rng: None,
+        }
+    }
+
+    /// A mover with a generator of its own, so that its moves can be replayed exactly whatever
+    /// else draws random numbers in the meantime.
+    pub fn new_seeded(seed: u64) -> Self {
+        Self {
+            rng: Some(ChaCha8Rng::seed_from_u64(seed)),
         }
     }
 }
 impl Agent for RandomMover {
-    fn next_move(&self, gs: &GameState) -> Move {
-        let moves = get_legal(&gs);
-        let mut rng = rand::thread_rng();
-        return moves[rng.gen_range(0..moves.len())]
+    fn label(&self) -> String {
+        "random".to_string()
+    }
+
+    fn next_move(&mut self, gs: &GameState) -> Move {
+        let moves = get_legal(gs);
+        let index = match self.rng.as_mut() {
+            Some(rng) => rng.gen_range(0..moves.len()),
+            None => with_rng(|rng| rng.gen_range(0..moves.len())),
+        };
+        moves[index]
+    }
+}
+
+/// Wraps any agent with a minimum competence guarantee: a move that lets the opponent win on
+/// the spot is replaced by the best safe move, if there is one.
+pub struct SafetyNetAgent {
+    inner: Box<dyn Agent>,
+}
+
+impl SafetyNetAgent {
+    pub fn new(inner: Box<dyn Agent>) -> Self {
+        Self { inner }
+    }
+
+    fn is_safe(gs: &GameState, mov: Move) -> bool {
+        let child = play(mov, gs).unwrap();
+        result(&child).is_some() || winning_moves(&child, child.turn).is_empty()
+    }
+}
+
+impl Agent for SafetyNetAgent {
+    fn label(&self) -> String {
+        format!("safety-net({})", self.inner.label())
+    }
+
+    fn next_move(&mut self, gs: &GameState) -> Move {
+        let mov = self.inner.next_move(gs);
+        if Self::is_safe(gs, mov) {
+            return mov;
+        }
+        let params = EvalParams::default();
+        get_legal(gs).into_iter()
+            .filter(|mov| Self::is_safe(gs, *mov))
+            .max_by(|a, b| eval_move(gs, *a, &params).total_cmp(&eval_move(gs, *b, &params)))
+            .unwrap_or(mov)
+    }
+}
+
+/// Weakens any agent for beginners: with probability `epsilon` a uniformly random legal move is
+/// played instead of the inner agent's choice. It has its own seeded RNG, so a game against it can
+/// be replayed.
+pub struct EpsilonGreedyAgent {
+    inner: Box<dyn Agent>,
+    epsilon: f64,
+    rng: ChaCha8Rng,
+}
+
+impl EpsilonGreedyAgent {
+    pub fn new(inner: Box<dyn Agent>, epsilon: f64, seed: u64) -> Self {
+        Self {
+            inner,
+            epsilon,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Agent for EpsilonGreedyAgent {
+    fn label(&self) -> String {
+        format!("epsilon-{}({})", self.epsilon, self.inner.label())
+    }
+
+    fn next_move(&mut self, gs: &GameState) -> Move {
+        if self.rng.gen_bool(self.epsilon) {
+            let moves = get_legal(gs);
+            moves[self.rng.gen_range(0..moves.len())]
+        } else {
+            self.inner.next_move(gs)
+        }
+    }
+}
+
+/// A search result from the perspective of the side to move. Mate distances count the moves of
+/// the side that delivers it, so winning with the next disc is `MateIn(1)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Score {
+    Heuristic(f32),
+    MateIn(u32),
+    MatedIn(u32),
+}
+
+/// Search value of a won position. A win found `n` plies away is worth `MATE - n`, which keeps
+/// mate values far above anything `utility` can return.
+const MATE: f32 = 1_000_000.0;
+const MATE_BOUND: f32 = MATE - 1000.0;
+
+impl Score {
+    pub(crate) fn from_value(value: f32, turn: Player) -> Self {
+        let value = if turn == Player::P1 { value } else { -value };
+        if value > MATE_BOUND {
+            Score::MateIn(((MATE - value) as u32).div_ceil(2))
+        } else if value < -MATE_BOUND {
+            Score::MatedIn((MATE + value) as u32 / 2)
+        } else {
+            Score::Heuristic(value)
+        }
+    }
+
+    /// The score as a search value for the side to move, so that scores can be compared: any win
+    /// beats any heuristic value, and a quicker one beats a slower one.
+    pub fn value(&self) -> f32 {
+        match self {
+            Score::Heuristic(value) => *value,
+            Score::MateIn(moves) => MATE - (2 * moves).saturating_sub(1) as f32,
+            Score::MatedIn(moves) => (2 * moves) as f32 - MATE,
+        }
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Score::Heuristic(value) => write!(f, "{:+.1}", value),
+            Score::MateIn(moves) => write!(f, "mate in {}", moves),
+            Score::MatedIn(moves) => write!(f, "mated in {}", moves),
+        }
+    }
+}
+
+/// Killer moves and the history table, learned from the cutoffs of a search to try the moves that
+/// refuted other positions first.
+#[derive(Clone, Debug, Default)]
+pub struct MoveOrdering {
+    /// The last two moves that caused a cutoff with this many discs on the board.
+    killers: Vec<[Option<Move>; 2]>,
+    /// How much each move has cut off, weighted towards cutoffs far from the horizon.
+    history: HashMap<Move, u32>,
+    /// A move list for each remaining depth, handed back after every node so that the next one
+    /// at that depth fills it again instead of allocating its own.
+    move_lists: Vec<Vec<Move>>,
+}
+
+impl MoveOrdering {
+    fn order(&self, moves: &mut [Move], discs: usize) {
+        let killers = self.killers.get(discs).copied().unwrap_or_default();
+        moves.sort_by_key(|mov| Reverse((killers.contains(&Some(*mov)), self.history.get(mov).copied().unwrap_or(0))));
+    }
+
+    fn record_cutoff(&mut self, mov: Move, discs: usize, depth: i32) {
+        if self.killers.len() <= discs {
+            self.killers.resize(discs + 1, [None; 2]);
+        }
+        let killers = &mut self.killers[discs];
+        if killers[0] != Some(mov) {
+            killers[1] = killers[0];
+            killers[0] = Some(mov);
+        }
+        *self.history.entry(mov).or_insert(0) += (depth * depth) as u32;
+    }
+
+    fn take_move_list(&mut self, depth: i32) -> Vec<Move> {
+        self.move_lists.get_mut(depth as usize).map(std::mem::take).unwrap_or_default()
+    }
+
+    fn return_move_list(&mut self, depth: i32, moves: Vec<Move>) {
+        let depth = depth as usize;
+        if self.move_lists.len() <= depth {
+            self.move_lists.resize_with(depth + 1, Vec::new);
+        }
+        self.move_lists[depth] = moves;
+    }
+}
+
+/// Mate values are relative to the node they are found in, so they move one ply closer to zero
+/// on the way up to the parent.
+pub(crate) fn to_parent(value: f32) -> f32 {
+    if value > MATE_BOUND {
+        value - 1.0
+    } else if value < -MATE_BOUND {
+        value + 1.0
+    } else {
+        value
+    }
+}
+
+/// Whether a child's value means the move into it won the game on the spot. Nothing can score
+/// better, so the remaining siblings need not be searched.
+fn wins_immediately(value: f32, is_max: bool) -> bool {
+    value == if is_max { MATE - 1.0 } else { 1.0 - MATE }
+}
+
+pub(crate) fn leaf_value(gs: &GameState, evaluator: &dyn Evaluator) -> f32 {
+    match result(gs) {
+        Some(GameResult::Win(Player::P1)) => MATE,
+        Some(GameResult::Win(Player::P2)) => -MATE,
+        Some(GameResult::Draw) => 0.0,
+        None => evaluator.evaluate(gs),
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SearchStats {
+    pub nodes: u64,
+    /// Table entries deep enough to be used, whether or not they settled the node on their own.
+    pub tt_hits: u64,
+    /// Nodes whose search stopped early because the window closed.
+    pub beta_cutoffs: u64,
+    /// The last depth of iterative deepening that completed.
+    pub max_depth_reached: i32,
+    pub elapsed: Duration,
+    /// Best root move, score, nodes and time of each completed depth of iterative deepening.
+    pub depths: Vec<DepthReport>,
+}
+
+impl SearchStats {
+    /// Adds the counts of a search run separately, such as one root move of a parallel search.
+    #[cfg(feature = "parallel")]
+    fn absorb(&mut self, other: &SearchStats) {
+        self.nodes += other.nodes;
+        self.tt_hits += other.tt_hits;
+        self.beta_cutoffs += other.beta_cutoffs;
+    }
+}
+
+/// What one completed depth of iterative deepening found, and what it cost.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DepthReport {
+    pub depth: i32,
+    pub best: Move,
+    pub score: Score,
+    pub nodes: u64,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for DepthReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "depth {} best {} score {} nodes {} time {:?}", self.depth, self.best.col(), self.score, self.nodes, self.elapsed)
+    }
+}
+
+/// Called with each `DepthReport` as the search goes.
+pub type DepthCallback = Box<dyn Fn(&DepthReport) + Send + Sync>;
+
+/// Setting this environment variable prints a `DepthReport` line to stderr for every depth
+/// searched by an agent without a callback of its own.
+const SEARCH_LOG_VAR: &str = "C4_SEARCH_LOG";
+
+pub(crate) const DEFAULT_DEPTH : i32 = 5;
+/// How many forced blocks the quiescence extension follows past the horizon.
+const QUIESCENCE_PLIES: i32 = 2;
+
+fn sort_center_out(moves: &mut [Move], cols: usize) {
+    let priority = center_out(cols);
+    moves.sort_by_key(|mov| priority.iter().position(|&col| col == mov.col()));
+}
+
+/// The moves a search needs to try. With symmetry pruning, a left-right symmetric board only
+/// needs the columns up to and including the center. The columns are picked by number rather than
+/// by position in the list, so reordering the moves afterwards cannot lose one.
+pub(crate) fn search_moves(gs: &GameState, symmetry_pruning: bool) -> Vec<Move> {
+    let mut moves = Vec::new();
+    search_moves_into(gs, symmetry_pruning, &mut moves);
+    moves
+}
+
+/// `search_moves` written into `moves`, which is cleared first.
+fn search_moves_into(gs: &GameState, symmetry_pruning: bool, moves: &mut Vec<Move>) {
+    let pruned = symmetry_pruning && is_symmetrical(gs);
+    moves.clear();
+    moves.extend(legal_moves(gs).filter(|mov| !pruned || 2 * mov.col() < gs.cols));
+    debug_assert!(!pruned || get_legal(gs).iter().all(|mov| moves.contains(mov) || moves.contains(&Move::new(mov.row(), gs.cols - 1 - mov.col()))));
+}
+
+#[derive(EnumIter, Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Expert,
+}
+
+pub struct MinMaxAgent {
+    depth: i32,
+    symmetry_pruning: bool,
+    teaching_bias: bool,
+    make_unmake: bool,
+    reuse_move_lists: bool,
+    killer_moves: bool,
+    center_first: bool,
+    threat_ordering: bool,
+    quiescence: bool,
+    /// Kept between moves, so positions searched on earlier turns need not be searched again.
+    table: TranspositionTable,
+    time_limit: Option<Duration>,
+    /// What is left of the agent's clock for the rest of the game, shared out over its moves.
+    game_time: Option<Duration>,
+    deadline: Mutex<Option<Instant>>,
+    timed_out: AtomicBool,
+    aspiration_window: Option<f32>,
+    #[cfg(feature = "parallel")]
+    parallel_root: bool,
+    evaluator: Box<dyn Evaluator>,
+    on_depth: Option<DepthCallback>,
+    last_stats: SearchStats,
+}
+
+impl Default for MinMaxAgent {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-pub struct MinMaxAgent {}
 impl MinMaxAgent {
     pub fn new() -> Self {
+        Self::new_with_depth(DEFAULT_DEPTH)
+    }
+
+    /// A search needs at least one ply to find a move, so `depth` must be 1 or more.
+    pub fn new_with_depth(depth: i32) -> Self {
+        assert!(depth >= 1, "search depth must be at least 1, got {}", depth);
         Self {
+            depth,
+            symmetry_pruning: true,
+            teaching_bias: false,
+            make_unmake: true,
+            reuse_move_lists: true,
+            killer_moves: true,
+            center_first: false,
+            threat_ordering: false,
+            quiescence: false,
+            table: TranspositionTable::new(),
+            time_limit: None,
+            game_time: None,
+            deadline: Mutex::new(None),
+            timed_out: AtomicBool::new(false),
+            aspiration_window: None,
+            #[cfg(feature = "parallel")]
+            parallel_root: false,
+            evaluator: Box::new(WindowEvaluator),
+            on_depth: None,
+            last_stats: SearchStats::default(),
         }
     }
-}
+
+    /// Easy looks 2 plies ahead, Medium 4 and Hard 7. Expert searches as deep as it can get in
+    /// 10 seconds, up to the whole game.
+    pub fn from_difficulty(difficulty: Difficulty, rows: usize, cols: usize) -> Self {
+        match difficulty {
+            Difficulty::Easy => Self::new_with_depth(2),
+            Difficulty::Medium => Self::new_with_depth(4),
+            Difficulty::Hard => Self::new_with_depth(7),
+            Difficulty::Expert => Self::new_with_depth((rows * cols) as i32).with_time_limit(Some(Duration::from_secs(10))),
+        }
+    }
+
+    /// Stops deepening once the time is up and plays the best move of the last completed depth.
+    pub fn with_time_limit(mut self, time_limit: Option<Duration>) -> Self {
+        self.time_limit = time_limit;
+        self
+    }
+
+    /// Gives the agent a clock for the whole game. Each move gets an even share of what is left
+    /// over the moves the agent may still have to make, so moves get more time as the board fills.
+    pub fn with_game_time(mut self, game_time: Option<Duration>) -> Self {
+        self.game_time = game_time;
+        self
+    }
+
+    /// Scores the leaves of the search with `evaluator` instead of the `WindowEvaluator`.
+    pub fn with_evaluator(mut self, evaluator: Box<dyn Evaluator>) -> Self {
+        self.evaluator = evaluator;
+        self
+    }
+
+    /// Reports every completed depth of iterative deepening to `on_depth`, e.g. to log the search.
+    pub fn with_depth_callback(mut self, on_depth: Option<DepthCallback>) -> Self {
+        self.on_depth = on_depth;
+        self
+    }
+
+    pub fn depth(&self) -> i32 {
+        self.depth
+    }
+
+    /// The statistics of the last search `next_move` made.
+    pub fn last_search_stats(&self) -> SearchStats {
+        self.last_stats.clone()
+    }
+
+    fn report(&self, report: &DepthReport) {
+        match &self.on_depth {
+            Some(on_depth) => on_depth(report),
+            None if std::env::var_os(SEARCH_LOG_VAR).is_some() => eprintln!("{}", report),
+            None => {}
+        }
+    }
+
+    pub fn time_limit(&self) -> Option<Duration> {
+        self.time_limit
+    }
+
+    /// The time the agent will spend on a move in `gs`: its share of the game clock, but no more
+    /// than the time limit.
+    pub fn time_budget(&self, gs: &GameState) -> Option<Duration> {
+        let own_moves = (gs.rows() * gs.cols() - gs.blocked_count() - disc_count(gs)).div_ceil(2).max(1);
+        let share = self.game_time.map(|game_time| game_time / own_moves as u32);
+        match (share, self.time_limit) {
+            (Some(share), Some(limit)) => Some(share.min(limit)),
+            (share, limit) => share.or(limit),
+        }
+    }
+
+    /// Checked every so many nodes, since reading the clock on every node would slow the search.
+    fn out_of_time(&self, nodes: u64) -> bool {
+        if !self.timed_out() && nodes.is_multiple_of(1024) {
+            if let Some(deadline) = *self.deadline.lock().unwrap() {
+                self.timed_out.store(Instant::now() >= deadline, Ordering::Relaxed);
+            }
+        }
+        self.timed_out()
+    }
+
+    fn timed_out(&self) -> bool {
+        self.timed_out.load(Ordering::Relaxed)
+    }
+
+    /// On a left-right symmetric board a move and its mirror are equivalent, so only the
+    /// columns up to and including the center need searching.
+    pub fn with_symmetry_pruning(mut self, symmetry_pruning: bool) -> Self {
+        self.symmetry_pruning = symmetry_pruning;
+        self
+    }
+
+    /// Among equally scored root moves, prefer one that creates a fork, so that a learner gets
+    /// to see the motif rather than just the quickest win.
+    pub fn with_teaching_bias(mut self, teaching_bias: bool) -> Self {
+        self.teaching_bias = teaching_bias;
+        self
+    }
+
+    /// Starts each depth of the iterative deepening with a window of `margin` around the value of
+    /// the depth before, searching again with a wider window when the value falls outside.
+    pub fn with_aspiration_window(mut self, margin: Option<f32>) -> Self {
+        self.aspiration_window = margin;
+        self
+    }
+
+    /// Searches the root moves in parallel, one thread per move. The teaching bias needs the root
+    /// moves compared in order, so it keeps the search sequential.
+    #[cfg(feature = "parallel")]
+    pub fn with_parallel_root(mut self, parallel_root: bool) -> Self {
+        self.parallel_root = parallel_root;
+        self
+    }
+
+    /// Searches by making and unmaking moves on a single board instead of cloning the board for
+    /// every child. Both give the same results; the clone-based search is kept for benchmarking.
+    pub fn with_make_unmake(mut self, make_unmake: bool) -> Self {
+        self.make_unmake = make_unmake;
+        self
+    }
+
+    /// Fills one move list per depth over and over instead of collecting a new one at every node.
+    /// Both give the same results; the allocating search is kept for benchmarking.
+    pub fn with_reused_move_lists(mut self, reuse_move_lists: bool) -> Self {
+        self.reuse_move_lists = reuse_move_lists;
+        self
+    }
+
+    /// Tries killer moves and moves with a good history first, which finds cutoffs sooner than
+    /// going through the columns in order.
+    pub fn with_killer_moves(mut self, killer_moves: bool) -> Self {
+        self.killer_moves = killer_moves;
+        self
+    }
+
+    /// Tries the columns from the center out rather than from left to right, before killer moves
+    /// and history reorder them. Central moves tend to be the best, so cutoffs come sooner.
+    pub fn with_center_first(mut self, center_first: bool) -> Self {
+        self.center_first = center_first;
+        self
+    }
+
+    /// Tries immediate wins before anything else and moves that hand the opponent a win last,
+    /// whatever killer moves and history say. Classifying the moves costs a few plies of lookahead
+    /// at every node.
+    pub fn with_threat_ordering(mut self, threat_ordering: bool) -> Self {
+        self.threat_ordering = threat_ordering;
+        self
+    }
+
+    /// Looks past the horizon when the side to move can win on the spot or has to block, so that
+    /// the search does not stop one ply short of a forced loss.
+    pub fn with_quiescence(mut self, quiescence: bool) -> Self {
+        self.quiescence = quiescence;
+        self
+    }
+
+    fn with_child<T>(&self, gs: &mut GameState, mov: Move, f: impl FnOnce(&mut GameState) -> T) -> T {
+        if self.make_unmake {
+            make_move(mov, gs);
+            let value = f(gs);
+            unmake_move(mov, gs);
+            value
+        } else {
+            f(&mut play(mov, gs).unwrap())
+        }
+    }
+
+    fn moves(&self, gs: &GameState, depth: i32, ordering: &mut MoveOrdering) -> Vec<Move> {
+        let mut moves = if self.reuse_move_lists { ordering.take_move_list(depth) } else { Vec::new() };
+        search_moves_into(gs, self.symmetry_pruning, &mut moves);
+        if self.center_first {
+            sort_center_out(&mut moves, gs.cols());
+        }
+        moves
+    }
+
+    /// The root goes from the center out, with immediate wins first. A move only replaces the
+    /// best one when it scores strictly better, so of the moves that tie for best the most central
+    /// is played, and the left one of two equally central columns.
+    fn root_moves(&self, gs: &GameState) -> Vec<Move> {
+        let mut moves = search_moves(gs, self.symmetry_pruning);
+        sort_center_out(&mut moves, gs.cols());
+        let wins = winning_moves(gs, gs.turn);
+        moves.sort_by_key(|mov| !wins.contains(mov));
+        moves
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn min_max(&self, gs: &mut GameState, depth: i32, mut alpha: f32, mut beta: f32, table: &mut TranspositionTable, ordering: &mut MoveOrdering, stats: &mut SearchStats) -> f32 {
+        stats.nodes += 1;
+        if self.out_of_time(stats.nodes) {
+            return 0.0;
+        }
+        if depth == 0 && self.quiescence {
+            return self.quiesce(gs, QUIESCENCE_PLIES, stats);
+        }
+        if depth == 0 || result(gs).is_some() || !has_legal_move(gs) {
+            return leaf_value(gs, self.evaluator.as_ref());
+        }
+        // Mirrored positions have the same value, so they share an entry.
+        let (key, packed) = gs.canonical_key();
+        if let Some(entry) = table.probe_packed(key, &packed) {
+            if entry.depth >= depth {
+                stats.tt_hits += 1;
+                match entry.bound {
+                    Bound::Exact(value) => return value,
+                    Bound::LowerBound(value) => alpha = alpha.max(value),
+                    Bound::UpperBound(value) => beta = beta.min(value),
+                }
+                if alpha >= beta {
+                    return entry.bound.value();
+                }
+            }
+        }
+        // Classified against the window actually searched, which a stored bound may have narrowed.
+        let (alpha_orig, beta_orig) = (alpha, beta);
+        let is_max = gs.turn == Player::P1;
+        let mut best = if is_max { f32::NEG_INFINITY } else { f32::INFINITY };
+        let mut moves = self.moves(gs, depth, ordering);
+        let discs = disc_count(gs);
+        if self.killer_moves {
+            ordering.order(&mut moves, discs);
+        }
+        if self.threat_ordering {
+            let classes = classify_moves(gs);
+            moves.sort_by_key(|mov| match classes.iter().find(|(classified, _)| classified == mov).map(|(_, class)| *class) {
+                Some(MoveClass::ImmediateWin) => 0,
+                Some(MoveClass::AllowsOpponentWin) => 2,
+                _ => 1,
+            });
+        }
+        let mut timed_out = false;
+        for &mov in &moves {
+            let value = to_parent(self.with_child(gs, mov, |child| self.min_max(child, depth - 1, alpha, beta, table, ordering, stats)));
+            if self.timed_out() {
+                timed_out = true;
+                break;
+            }
+            if is_max {
+                best = best.max(value);
+                alpha = alpha.max(best);
+            } else {
+                best = best.min(value);
+                beta = beta.min(best);
+            }
+            if alpha >= beta {
+                stats.beta_cutoffs += 1;
+                if self.killer_moves {
+                    ordering.record_cutoff(mov, discs, depth);
+                }
+                break;
+            }
+            if wins_immediately(value, is_max) {
+                break;
+            }
+        }
+        if self.reuse_move_lists {
+            ordering.return_move_list(depth, moves);
+        }
+        // An unfinished search must not leave its made-up values in the table.
+        if timed_out {
+            return 0.0;
+        }
+        let bound = if best <= alpha_orig {
+            Bound::UpperBound(best)
+        } else if best >= beta_orig {
+            Bound::LowerBound(best)
+        } else {
+            Bound::Exact(best)
+        };
+        table.store_packed(key, packed, discs, depth, bound);
+        best
+    }
+
+    /// The value of a horizon node, extended while the side to move has an immediate win or a
+    /// single forced block, for at most `plies` blocks. Two open threats against the side to move
+    /// lose whatever it plays.
+    fn quiesce(&self, gs: &mut GameState, plies: i32, stats: &mut SearchStats) -> f32 {
+        if plies == 0 || result(gs).is_some() {
+            return leaf_value(gs, self.evaluator.as_ref());
+        }
+        let mate = if gs.turn == Player::P1 { MATE } else { -MATE };
+        if !winning_moves(gs, gs.turn).is_empty() {
+            return to_parent(mate);
+        }
+        match winning_moves(gs, next_turn(gs.turn)).as_slice() {
+            [] => leaf_value(gs, self.evaluator.as_ref()),
+            [block] => {
+                stats.nodes += 1;
+                to_parent(self.with_child(gs, *block, |child| self.quiesce(child, plies - 1, stats)))
+            }
+            _ => to_parent(to_parent(-mate)),
+        }
+    }
+
+    fn search(&self, gs: &GameState, depth: i32, table: &mut TranspositionTable, ordering: &mut MoveOrdering, stats: &mut SearchStats) -> Option<(Move, f32)> {
+        self.search_window(gs, depth, f32::NEG_INFINITY, f32::INFINITY, table, ordering, stats)
+    }
+
+    /// The root search within `alpha` and `beta`. A value at or outside the window only bounds
+    /// the true value, and the move that goes with it cannot be trusted. `None` when the side to
+    /// move has no legal move.
+    #[allow(clippy::too_many_arguments)]
+    fn search_window(&self, gs: &GameState, depth: i32, mut alpha: f32, mut beta: f32, table: &mut TranspositionTable, ordering: &mut MoveOrdering, stats: &mut SearchStats) -> Option<(Move, f32)> {
+        stats.nodes += 1;
+        let is_max = gs.turn == Player::P1;
+        let moves = self.root_moves(gs);
+        #[cfg(feature = "parallel")]
+        if self.parallel_root && !self.teaching_bias {
+            return self.search_parallel(gs, depth, moves, stats);
+        }
+        let first = *moves.first()?;
+        let mut best = (first, if is_max { f32::NEG_INFINITY } else { f32::INFINITY });
+        let mut state = gs.clone();
+        for mov in moves {
+            let value = to_parent(self.with_child(&mut state, mov, |child| self.min_max(child, depth - 1, alpha, beta, table, ordering, stats)));
+            // A learner should see the fork even when a quicker win exists, so the teaching bias
+            // does not tell mates apart by distance.
+            let rank = |value: f32| if self.teaching_bias && value.is_finite() && value.abs() > MATE_BOUND { value.signum() * MATE } else { value };
+            let better = if is_max { rank(value) > rank(best.1) } else { rank(value) < rank(best.1) };
+            let teaching_tie = self.teaching_bias && rank(value) == rank(best.1)
+                && !creates_fork(gs, gs.turn, best.0) && creates_fork(gs, gs.turn, mov);
+            if better || teaching_tie {
+                best = (mov, value);
+            }
+            // Ties can only be told apart with exact values, so the teaching bias searches every
+            // root move with the full window, and looks past an immediate win for a fork.
+            if !self.teaching_bias {
+                if wins_immediately(value, is_max) {
+                    break;
+                }
+                if is_max {
+                    alpha = alpha.max(value);
+                } else {
+                    beta = beta.min(value);
+                }
+                if alpha >= beta {
+                    stats.beta_cutoffs += 1;
+                    break;
+                }
+            }
+        }
+        Some(best)
+    }
+
+    /// Searches a window of `margin` around `guess`, widening the side that fails until the value
+    /// falls inside.
+    #[allow(clippy::too_many_arguments)]
+    fn aspiration_search(&self, gs: &GameState, depth: i32, guess: f32, margin: f32, table: &mut TranspositionTable, ordering: &mut MoveOrdering, stats: &mut SearchStats) -> Option<(Move, f32)> {
+        let (mut alpha, mut beta) = (guess - margin, guess + margin);
+        loop {
+            let (mov, value) = self.search_window(gs, depth, alpha, beta, table, ordering, stats)?;
+            if self.timed_out() {
+                return Some((mov, value));
+            }
+            if value <= alpha {
+                alpha = f32::NEG_INFINITY;
+            } else if value >= beta {
+                beta = f32::INFINITY;
+            } else {
+                return Some((mov, value));
+            }
+        }
+    }
+
+    /// Searches every root move on its own thread with the full window and a private table, so
+    /// the threads share nothing but the clock. The best move is the same as the sequential
+    /// search's, but the cutoffs between root moves are lost. `None` when there are no moves.
+    #[cfg(feature = "parallel")]
+    fn search_parallel(&self, gs: &GameState, depth: i32, moves: Vec<Move>, stats: &mut SearchStats) -> Option<(Move, f32)> {
+        let results: Vec<(Move, f32, SearchStats)> = moves.into_par_iter().map(|mov| {
+            let mut child_stats = SearchStats::default();
+            let mut child = play(mov, gs).unwrap();
+            let value = to_parent(self.min_max(&mut child, depth - 1, f32::NEG_INFINITY, f32::INFINITY, &mut TranspositionTable::new(), &mut MoveOrdering::default(), &mut child_stats));
+            (mov, value, child_stats)
+        }).collect();
+        for (_, _, child_stats) in &results {
+            stats.absorb(child_stats);
+        }
+        let is_max = gs.turn == Player::P1;
+        // The first of equal moves is kept, as in the sequential search.
+        results.into_iter().map(|(mov, value, _)| (mov, value)).reduce(|best, (mov, value)| {
+            if (is_max && value > best.1) || (!is_max && value < best.1) { (mov, value) } else { best }
+        })
+    }
+
+    /// Searches with iterative deepening up to the agent's depth, recording the best move found at
+    /// each depth. A decided position settles on one move, while flip-flopping between depths is
+    /// expected on balanced positions where several moves score almost the same. Panics when the
+    /// side to move has no legal move, which a `GameSession` never asks for.
+    pub fn next_move_with_stats(&mut self, gs: &GameState) -> (Move, Score, SearchStats) {
+        let mut stats = SearchStats::default();
+        let mut table = std::mem::take(&mut self.table);
+        table.retain_reachable(gs);
+        let start = Instant::now();
+        let (mov, score) = self.deepen(gs, self.depth, &mut table, &mut stats).expect("no legal move to search");
+        stats.elapsed = start.elapsed();
+        self.game_time = self.game_time.map(|game_time| game_time.saturating_sub(start.elapsed()));
+        self.table = table;
+        self.last_stats = stats.clone();
+        (mov, score, stats)
+    }
+
+    /// The move the agent would play and how good it thinks the position is, searched with a
+    /// fresh table so that asking does not change the agent. `None` when there is no legal move.
+    pub fn analyze(&self, gs: &GameState) -> Option<(Move, Score)> {
+        self.deepen(gs, self.depth, &mut TranspositionTable::new(), &mut SearchStats::default())
+    }
+
+    /// The score of every legal move, searched to the agent's depth within its time limit.
+    pub fn analyze_all(&self, gs: &GameState) -> Vec<(Move, Score)> {
+        let mut table = TranspositionTable::new();
+        let mut ordering = MoveOrdering::default();
+        let mut stats = SearchStats::default();
+        self.start_clock(gs);
+        children(gs).map(|(mov, mut child)| {
+            let mut value = to_parent(leaf_value(&child, self.evaluator.as_ref()));
+            for depth in 2..=self.depth {
+                let deeper = to_parent(self.min_max(&mut child, depth - 1, f32::NEG_INFINITY, f32::INFINITY, &mut table, &mut ordering, &mut stats));
+                if self.timed_out() {
+                    break;
+                }
+                value = deeper;
+            }
+            (mov, Score::from_value(value, gs.turn))
+        }).collect()
+    }
+
+    /// The line the agent expects both sides to play, down to the depth it searched. The first move
+    /// is the one `analyze` picks, and each reply comes from a search of what is left of the depth.
+    pub fn principal_variation(&self, gs: &GameState) -> Vec<Move> {
+        let mut table = TranspositionTable::new();
+        let mut stats = SearchStats::default();
+        let Some((first, _)) = self.deepen(gs, self.depth, &mut table, &mut stats) else {
+            return Vec::new();
+        };
+        let reached = stats.depths.last().map_or(0, |report| report.depth);
+        let mut ordering = MoveOrdering::default();
+        let mut line = vec![first];
+        let mut state = play(first, gs).unwrap();
+        for depth in (1..reached).rev() {
+            if result(&state).is_some() {
+                break;
+            }
+            // Disabled columns can leave a side without a move before the game is decided.
+            let Some((mov, _)) = self.search(&state, depth, &mut table, &mut ordering, &mut stats) else {
+                break;
+            };
+            if self.timed_out() {
+                break;
+            }
+            line.push(mov);
+            make_move(mov, &mut state);
+        }
+        line
+    }
+
+    fn start_clock(&self, gs: &GameState) {
+        *self.deadline.lock().unwrap() = self.time_budget(gs).map(|budget| Instant::now() + budget);
+        self.timed_out.store(false, Ordering::Relaxed);
+    }
+
+    /// The best move found by the deepest search that completed, or `None` if none did, because
+    /// there is no legal move or `max_depth` is below 1.
+    fn deepen(&self, gs: &GameState, max_depth: i32, table: &mut TranspositionTable, stats: &mut SearchStats) -> Option<(Move, Score)> {
+        self.start_clock(gs);
+        let mut best = None;
+        let mut values: Vec<f32> = Vec::new();
+        let mut ordering = MoveOrdering::default();
+        for depth in 1..=max_depth {
+            let depth_start = Instant::now();
+            let nodes_before = stats.nodes;
+            // Values swing with whoever moved last at the horizon, so the guess comes from the last
+            // depth with the same parity.
+            let guess = values.len().checked_sub(2).map(|i| values[i]);
+            let searched = match (self.aspiration_window, guess) {
+                // Mate scores jump by far more than any margin from one depth to the next.
+                (Some(margin), Some(guess)) if !self.teaching_bias && f32::abs(guess) < MATE_BOUND => {
+                    self.aspiration_search(gs, depth, guess, margin, table, &mut ordering, stats)
+                }
+                _ => self.search(gs, depth, table, &mut ordering, stats),
+            };
+            let Some((mov, value)) = searched else {
+                break;
+            };
+            if self.timed_out() && !stats.depths.is_empty() {
+                break;
+            }
+            values.push(value);
+            let score = Score::from_value(value, gs.turn);
+            best = Some((mov, score));
+            stats.max_depth_reached = depth;
+            let report = DepthReport { depth, best: mov, score, nodes: stats.nodes - nodes_before, elapsed: depth_start.elapsed() };
+            self.report(&report);
+            stats.depths.push(report);
+            // The next depth takes at least as long as this one, so there is no use starting it
+            // without that much time left.
+            let deadline = *self.deadline.lock().unwrap();
+            if deadline.is_some_and(|deadline| Instant::now() + depth_start.elapsed() > deadline) {
+                break;
+            }
+        }
+        best
+    }
+
+    /// Runs an iterative deepening search up to `max_depth` with a fresh table, reporting the time
+    /// and nodes spent on each depth it completed within the time limit.
+    pub fn profile(&self, gs: &GameState, max_depth: i32) -> Vec<(i32, Duration, u64)> {
+        let mut stats = SearchStats::default();
+        self.deepen(gs, max_depth, &mut TranspositionTable::new(), &mut stats);
+        stats.depths.iter().map(|report| (report.depth, report.elapsed, report.nodes)).collect()
+    }
+}
 
 impl Agent for MinMaxAgent {
-    fn next_move(&self, gs: &GameState) -> Move {
-        let moves = get_legal(&gs);
-        let states : Vec<GameState>= moves.iter().map(|mov| play(*mov, gs).unwrap()).collect();
-        let utilities : Vec<f32> = states.iter().map(|state| eval(state)).collect();
-        moves[argmax(&utilities).0]
+    /// The depth, evaluator and time limit, so that differently set up agents keep apart in
+    /// tournament and rating tables.
+    fn label(&self) -> String {
+        let mut label = format!("minmax-d{}-{}", self.depth, self.evaluator.label());
+        if let Some(limit) = self.time_limit {
+            label += &format!("-t{:?}", limit);
+        }
+        label
+    }
+
+    fn next_move(&mut self, gs: &GameState) -> Move {
+        self.next_move_with_stats(gs).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use crate::game::{search_moves, Action, DepthReport, Difficulty, EpsilonGreedyAgent, MoveOrdering, ReplayError, leaf_value, to_parent, Agent, FirstPlayer, Game, GameEvent, GameSession, MinMaxAgent, RandomMover, SafetyNetAgent, Score, SearchStats, TimeoutPolicy};
+    use rand::prelude::*;
+    use rand::rngs::StdRng;
+    use crate::game_logic::{BoardError, GameResult, GameState, GravityDir, Move, MoveError, Player, children, disc_count, creates_fork, get_legal, get_random_position, is_symmetrical, play, play_col, result, winning_moves};
+    use crate::rng;
+    use crate::transposition::TranspositionTable;
+    use crate::evaluator::{Evaluator, WeightedWindowEvaluator, WindowEvaluator};
+
+    fn minmax_game(move_delay: Option<Duration>) -> Game {
+        let mut game = Game::new_with_agents(6, 7, Box::new(MinMaxAgent::new_with_depth(2)), Box::new(MinMaxAgent::new_with_depth(3)));
+        game.set_move_delay(move_delay);
+        game
+    }
+
+    #[test]
+    fn move_delay_only_affects_timing() {
+        let mut instant = minmax_game(None);
+        let mut delayed = minmax_game(Some(Duration::from_millis(1)));
+        assert_eq!(instant.start_game(), delayed.start_game());
+        assert!(instant.state() == delayed.state());
+    }
+
+    #[test]
+    fn profile_depths() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,2,0,0,0],
+                [0,0,1,1,0,0,0],
+                [0,2,1,2,0,0,0]
+            ]
+        );
+        let profile = MinMaxAgent::new().profile(&gs, 5);
+        assert_eq!(profile.len(), 5);
+        for (i, (depth, _, _)) in profile.iter().enumerate() {
+            assert_eq!(*depth, i as i32 + 1);
+        }
+        for window in profile.windows(2) {
+            assert!(window[0].2 <= window[1].2);
+        }
+    }
+
+    #[test]
+    fn best_move_stable_on_decided_position() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,2,0,0,0,0],
+                [0,0,2,0,0,0,0],
+                [0,0,2,1,1,1,0]
+            ]
+        );
+        let (mov, score, stats) = MinMaxAgent::new_with_depth(5).next_move_with_stats(&gs);
+        assert_eq!(mov, Move::new(5, 6));
+        assert_eq!(score, Score::MateIn(1));
+        assert_eq!(stats.depths.len(), 5);
+        for (i, report) in stats.depths.iter().enumerate() {
+            assert_eq!(report.depth, i as i32 + 1);
+            assert_eq!(report.best, mov);
+        }
+    }
+
+    fn random_symmetrical_position(rng: &mut StdRng, blocks: usize) -> GameState {
+        let mut gs = GameState::new();
+        for _ in 0..blocks {
+            let moves = get_legal(&gs);
+            let (first, second) = (moves[rng.gen_range(0..moves.len())], moves[rng.gen_range(0..moves.len())]);
+            let mut next = gs.clone();
+            for col in [first.col(), second.col(), gs.cols - 1 - first.col(), gs.cols - 1 - second.col()] {
+                match Move::drop(&next, col).and_then(|mov| play(mov, &next)) {
+                    Some(child) if result(&child).is_none() => next = child,
+                    _ => break,
+                }
+            }
+            if next.turn == gs.turn && is_symmetrical(&next) {
+                gs = next;
+            }
+        }
+        gs
+    }
+
+    #[test]
+    fn symmetry_pruning_preserves_value() {
+        let mut rng = StdRng::seed_from_u64(208);
+        let pruned = MinMaxAgent::new_with_depth(4);
+        let full = MinMaxAgent::new_with_depth(4).with_symmetry_pruning(false);
+        for _ in 0..50 {
+            let pairs = rng.gen_range(0..8);
+            let gs = random_symmetrical_position(&mut rng, pairs);
+            assert!(is_symmetrical(&gs));
+            let (_, pruned_value) = pruned.search(&gs, 4, &mut TranspositionTable::new(), &mut MoveOrdering::default(), &mut SearchStats::default()).unwrap();
+            let (_, full_value) = full.search(&gs, 4, &mut TranspositionTable::new(), &mut MoveOrdering::default(), &mut SearchStats::default()).unwrap();
+            assert_eq!(pruned_value, full_value, "{}", gs);
+        }
+    }
+
+    #[test]
+    fn symmetry_pruning_survives_reordering() {
+        let mut rng = StdRng::seed_from_u64(291);
+        let pruned = MinMaxAgent::new_with_depth(4);
+        let full = MinMaxAgent::new_with_depth(4).with_symmetry_pruning(false);
+        for _ in 0..30 {
+            let pairs = rng.gen_range(0..8);
+            let gs = random_symmetrical_position(&mut rng, pairs);
+            let kept = search_moves(&gs, true);
+            for mov in get_legal(&gs) {
+                let mirror = Move::new(mov.row(), gs.cols - 1 - mov.col());
+                assert!(kept.contains(&mov) || kept.contains(&mirror), "{:?} in {}", mov, gs);
+            }
+            // A history that favours the right half puts the dropped columns first everywhere.
+            let mut ordering = MoveOrdering::default();
+            for row in 0..gs.rows() {
+                for col in gs.cols / 2 + 1..gs.cols {
+                    ordering.record_cutoff(Move::new(row, col), 0, 10);
+                }
+            }
+            let (_, pruned_value) = pruned.search(&gs, 4, &mut TranspositionTable::new(), &mut ordering.clone(), &mut SearchStats::default()).unwrap();
+            let (_, full_value) = full.search(&gs, 4, &mut TranspositionTable::new(), &mut ordering, &mut SearchStats::default()).unwrap();
+            assert_eq!(pruned_value, full_value, "{}", gs);
+        }
+    }
+
+    #[test]
+    fn teaching_bias_prefers_fork() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [1,0,0,2,2,0,0],
+                [1,0,0,2,2,0,0],
+                [1,2,0,1,1,0,0]
+            ]
+        );
+        let plain = MinMaxAgent::new_with_depth(3);
+        let teaching = MinMaxAgent::new_with_depth(3).with_teaching_bias(true);
+        let (plain_move, plain_value) = plain.search(&gs, 3, &mut TranspositionTable::new(), &mut MoveOrdering::default(), &mut SearchStats::default()).unwrap();
+        let (teaching_move, teaching_value) = teaching.search(&gs, 3, &mut TranspositionTable::new(), &mut MoveOrdering::default(), &mut SearchStats::default()).unwrap();
+        assert_eq!(plain_move, Move::new(2, 0));
+        assert!(!creates_fork(&gs, gs.turn, plain_move));
+        assert!(creates_fork(&gs, gs.turn, teaching_move));
+        assert_eq!(Score::from_value(plain_value, gs.turn), Score::MateIn(1));
+        assert_eq!(Score::from_value(teaching_value, gs.turn), Score::MateIn(2));
+    }
+
+    #[test]
+    fn stops_at_a_winning_move() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,2],
+                [0,1,1,1,0,2,2]
+            ]
+        );
+        let (mov, score, stats) = MinMaxAgent::new_with_depth(3).next_move_with_stats(&gs);
+        // Column 4 wins as well as column 0 and is more central.
+        assert_eq!((mov, score), (Move::new(5, 4), Score::MateIn(1)));
+        // Each of the three depths only looks at the root and the winning move, which goes first.
+        assert!(stats.nodes < 1 + get_legal(&gs).len() as u64, "{}", stats.nodes);
+        assert_eq!(stats.nodes, 6);
+    }
+
+    #[test]
+    fn principal_variation_ends_in_mate() {
+        let gs = GameState::from_compact("7/5x1/5o1/x4x1/ooxo1o1/oxxxoxo x").unwrap();
+        let mut agent = MinMaxAgent::new_with_depth(7);
+        assert_eq!(agent.analyze(&gs).unwrap().1, Score::MateIn(3));
+        let line = agent.principal_variation(&gs);
+        assert!(line.len() >= 3, "{:?}", line);
+        assert_eq!(line[0], agent.next_move(&gs));
+        let end = line.iter().fold(gs, |state, mov| play(*mov, &state).unwrap());
+        assert_eq!(result(&end), Some(GameResult::Win(Player::P1)));
+    }
+
+    #[test]
+    fn analyzes_every_column() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,2],
+                [0,1,1,1,0,2,2]
+            ]
+        );
+        let agent = MinMaxAgent::new_with_depth(4);
+        assert_eq!(agent.analyze(&gs), Some((Move::new(5, 4), Score::MateIn(1))));
+        let scores = agent.analyze_all(&gs);
+        assert_eq!(scores.len(), 7);
+        for (mov, score) in scores {
+            // Any other move lets P2 block, but P1 still wins by playing the second threat.
+            let expected = if mov.col() == 0 || mov.col() == 4 { Score::MateIn(1) } else { Score::MateIn(2) };
+            assert_eq!(score, expected, "{:?}", mov);
+        }
+    }
+
+    #[test]
+    fn make_unmake_matches_clone_search() {
+        let mut rng = StdRng::seed_from_u64(218);
+        let mut in_place = MinMaxAgent::new_with_depth(4);
+        let mut cloning = MinMaxAgent::new_with_depth(4).with_make_unmake(false);
+        for _ in 0..20 {
+            let pairs = rng.gen_range(0..8);
+            let gs = random_symmetrical_position(&mut rng, pairs);
+            let (in_place_move, _, in_place_stats) = in_place.next_move_with_stats(&gs);
+            let (cloning_move, _, cloning_stats) = cloning.next_move_with_stats(&gs);
+            assert_eq!(in_place_move, cloning_move, "{}", gs);
+            assert_eq!(in_place_stats.nodes, cloning_stats.nodes);
+        }
+    }
+
+    #[test]
+    fn reused_move_lists_match_fresh_ones() {
+        crate::rng::seed(308);
+        for i in 0..20 {
+            let gs = get_random_position(&GameState::new(), i % 20);
+            if result(&gs).is_some() {
+                continue;
+            }
+            let (reused_move, reused_score, reused_stats) = MinMaxAgent::new_with_depth(5).next_move_with_stats(&gs);
+            let (fresh_move, fresh_score, fresh_stats) = MinMaxAgent::new_with_depth(5).with_reused_move_lists(false).next_move_with_stats(&gs);
+            assert_eq!((reused_move, reused_score), (fresh_move, fresh_score), "{}", gs);
+            assert_eq!(reused_stats.nodes, fresh_stats.nodes);
+        }
+        crate::rng::reset_rng();
+    }
+
+    #[test]
+    fn safety_net_avoids_hanging_a_four() {
+        let mut agent = SafetyNetAgent::new(Box::new(RandomMover::new()));
+        let hangs = |gs: &GameState, mov: Move| {
+            let child = play(mov, gs).unwrap();
+            result(&child).is_none() && !winning_moves(&child, child.turn).is_empty()
+        };
+        let mut rescued = 0;
+        for seed in 0..500 {
+            rng::seed(seed);
+            let gs = get_random_position(&GameState::new(), 8 + seed as usize % 24);
+            if result(&gs).is_some() {
+                continue;
+            }
+            let safe_exists = get_legal(&gs).into_iter().any(|mov| !hangs(&gs, mov));
+            if safe_exists && get_legal(&gs).into_iter().any(|mov| hangs(&gs, mov)) {
+                rescued += 1;
+            }
+            for _ in 0..10 {
+                let mov = agent.next_move(&gs);
+                assert!(!safe_exists || !hangs(&gs, mov), "{}", gs);
+            }
+        }
+        assert!(rescued > 0);
+    }
+
+    #[test]
+    fn reports_mate_and_heuristic_scores() {
+        // P1 plays (5,4), or (5,1) on the other side, and whichever end P2 blocks, the other
+        // completes the row.
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,2,2,0,0,0],
+                [0,0,1,1,0,0,0]
+            ]
+        );
+        let (mov, score, _) = MinMaxAgent::new_with_depth(5).next_move_with_stats(&gs);
+        assert_eq!(mov, Move::new(5, 4));
+        assert_eq!(score, Score::MateIn(2));
+        let defender = play(mov, &gs).unwrap();
+        let (_, score, _) = MinMaxAgent::new_with_depth(5).next_move_with_stats(&defender);
+        assert_eq!(score, Score::MatedIn(1));
+        let (_, score, _) = MinMaxAgent::new_with_depth(5).next_move_with_stats(&GameState::new());
+        assert!(matches!(score, Score::Heuristic(_)));
+    }
+
+    #[test]
+    fn table_is_kept_between_moves() {
+        let mut agent = MinMaxAgent::new_with_depth(7);
+        let gs = GameState::new();
+        let (mov, _, _) = agent.next_move_with_stats(&gs);
+        let mut replied = play(mov, &gs).unwrap();
+        replied = play(Move::drop(&replied, 3).unwrap(), &replied).unwrap();
+        let (reused_move, _, reused) = agent.next_move_with_stats(&replied);
+        let (fresh_move, _, fresh) = MinMaxAgent::new_with_depth(7).next_move_with_stats(&replied);
+        assert_eq!(reused_move, fresh_move);
+        // The shallow depths of iterative deepening are answered from the kept table. Stored bounds
+        // also change which cutoffs the move ordering learns, so a reply off the center saves less.
+        assert!(reused.nodes < fresh.nodes * 9 / 10, "{} vs {}", reused.nodes, fresh.nodes);
+    }
+
+    /// Plain minimax without pruning or a table, to check the search against.
+    fn reference_min_max(gs: &GameState, depth: i32) -> f32 {
+        if depth == 0 || result(gs).is_some() {
+            return leaf_value(gs, &WindowEvaluator);
+        }
+        let values = children(gs).map(|(_, child)| to_parent(reference_min_max(&child, depth - 1)));
+        match gs.turn {
+            Player::P1 => values.fold(f32::NEG_INFINITY, f32::max),
+            Player::P2 => values.fold(f32::INFINITY, f32::min),
+        }
+    }
+
+    #[test]
+    fn table_bounds_preserve_minimax_values() {
+        rng::seed(254);
+        let mut agent = MinMaxAgent::new_with_depth(4);
+        for i in 0..40 {
+            let gs = get_random_position(&GameState::new(), 6 + i % 20);
+            if result(&gs).is_some() {
+                continue;
+            }
+            let (mov, score, _) = agent.next_move_with_stats(&gs);
+            let expected = reference_min_max(&gs, 4);
+            assert_eq!(score, Score::from_value(expected, gs.turn), "{}", gs);
+            assert_eq!(to_parent(reference_min_max(&play(mov, &gs).unwrap(), 3)), expected, "{}", gs);
+        }
+        // Storing cut-off values as if they were exact makes a depth 5 search play column 1 here,
+        // missing that column 4 is the only move that mates.
+        let gs = GameState::from_compact("2x4/2x4/2x4/2o2oo/1ox2xx/oxo1oxo x").unwrap();
+        let (mov, score) = MinMaxAgent::new_with_depth(5).analyze(&gs).unwrap();
+        assert_eq!(mov, Move::drop(&gs, 4).unwrap());
+        assert!(matches!(score, Score::MateIn(_)), "{:?}", score);
+    }
+
+    #[test]
+    fn root_search_matches_minimax() {
+        rng::seed(255);
+        for i in 0..30 {
+            let gs = get_random_position(&GameState::new(), i);
+            if result(&gs).is_some() {
+                continue;
+            }
+            for depth in 1..=3 {
+                let agent = MinMaxAgent::new_with_depth(depth).with_symmetry_pruning(false);
+                let (mov, value) = agent.search(&gs, depth, &mut TranspositionTable::new(), &mut MoveOrdering::default(), &mut SearchStats::default()).unwrap();
+                assert_eq!(value, reference_min_max(&gs, depth), "{}", gs);
+                assert_eq!(to_parent(reference_min_max(&play(mov, &gs).unwrap(), depth - 1)), value, "{}", gs);
+            }
+        }
+        let mut stuck = GameState::new();
+        (0..stuck.cols()).for_each(|col| stuck.disable_column(col));
+        let agent = MinMaxAgent::new_with_depth(3);
+        assert_eq!(agent.search(&stuck, 3, &mut TranspositionTable::new(), &mut MoveOrdering::default(), &mut SearchStats::default()), None);
+        assert_eq!(agent.analyze(&stuck), None);
+        assert!(agent.principal_variation(&stuck).is_empty());
+        assert!(agent.profile(&GameState::new(), 0).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "search depth must be at least 1")]
+    fn depth_below_one_is_rejected() {
+        MinMaxAgent::new_with_depth(0);
+    }
+
+    #[test]
+    fn p2_minimizes_into_win_in_one() {
+        let mut gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,2],
+                [0,0,1,0,0,0,2],
+                [1,0,1,0,1,0,2]
+            ]
+        );
+        gs.turn = Player::P2;
+        assert_eq!(MinMaxAgent::new().next_move(&gs), Move::new(2, 6));
+    }
+
+    #[test]
+    fn p1_maximizes_into_win_in_one() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [1,0,0,0,0,0,0],
+                [1,0,0,0,2,0,0],
+                [1,0,2,0,2,0,0]
+            ]
+        );
+        assert_eq!(MinMaxAgent::new().next_move(&gs), Move::new(2, 0));
+    }
+
+    #[test]
+    fn random_movers_play_without_prompting() {
+        rng::seed(262);
+        let mut game = Game::new_with_agents(5, 6, Box::new(RandomMover::new()), Box::new(RandomMover::new()));
+        let res = game.start_game();
+        assert_eq!(result(game.state()), Some(res));
+        assert_eq!(game.play(Move::new(0, 0)), Err(MoveError::GameOver));
+    }
+
+    #[test]
+    fn undo_and_redo_moves() {
+        let mut game = Game::new_with_agents(6, 7, Box::new(RandomMover::new()), Box::new(RandomMover::new()));
+        let moves = [Move::new(5, 3), Move::new(4, 3), Move::new(5, 2)];
+        let mut states = vec![game.state().clone()];
+        for mov in moves {
+            assert_eq!(game.play(mov), Ok(None));
+            states.push(game.state().clone());
+        }
+        assert_eq!(game.undo(), Some(moves[2]));
+        assert_eq!(game.undo(), Some(moves[1]));
+        assert_eq!(game.state(), &states[1]);
+        assert_eq!(game.state().turn, Player::P2);
+        assert_eq!(game.history(), &moves[..1]);
+        assert_eq!(game.redo(), Some(moves[1]));
+        assert_eq!(game.redo(), Some(moves[2]));
+        assert_eq!(game.redo(), None);
+        assert_eq!(game.state(), &states[3]);
+        game.undo();
+        assert_eq!(game.play(Move::new(5, 4)), Ok(None));
+        assert_eq!(game.redo(), None);
+        assert_eq!(game.play(Move::new(0, 0)), Err(MoveError::Floating));
+    }
+
+    #[test]
+    fn replays_exported_moves() {
+        rng::seed(285);
+        let mut game = Game::new_with_agents(6, 7, Box::new(RandomMover::new()), Box::new(RandomMover::new()));
+        game.start_game();
+        let moves = game.export_moves();
+        assert_eq!(moves.split(' ').count(), game.history().len());
+        assert_eq!(Game::replay(&moves, 6, 7).as_ref(), Ok(game.state()));
+        assert_eq!(Game::replay(&(moves + " 0"), 6, 7), Err(ReplayError::GameOver { ply: game.history().len() }));
+    }
+
+    #[test]
+    fn replay_rejects_illegal_moves() {
+        assert_eq!(Game::replay("3 7", 6, 7), Err(ReplayError::Illegal { ply: 1, col: 7 }));
+        assert_eq!(Game::replay("0 0 0 0 0 0 0", 6, 7), Err(ReplayError::Illegal { ply: 6, col: 0 }));
+        assert_eq!(Game::replay("3 x", 6, 7), Err(ReplayError::NotAColumn("x".to_string())));
+        assert_eq!(Game::replay(" 3  4 ", 6, 7).map(|gs| disc_count(&gs)), Ok(2));
+    }
+
+    #[test]
+    fn session_plays_columns() {
+        let mut session = GameSession::new(6, 7, Box::new(RandomMover::new()), Box::new(RandomMover::new()));
+        for col in [0, 1, 0, 1, 0, 1] {
+            assert_eq!(session.apply_column(col), Ok(None));
+        }
+        assert_eq!(session.apply_column(7), Err(MoveError::OffBoard));
+        assert_eq!(session.apply_column(0), Ok(Some(GameResult::Win(Player::P1))));
+        assert_eq!(session.result(), Some(GameResult::Win(Player::P1)));
+        assert_eq!(session.apply_column(2), Err(MoveError::GameOver));
+        assert_eq!(session.agent_move(), Err(MoveError::GameOver));
+        assert_eq!(session.history().len(), 7);
+        assert_eq!(session.undo(), Some(Move::new(2, 0)));
+        assert_eq!(session.result(), None);
+        assert_eq!(session.current_state().turn, Player::P1);
+
+        let mut full = GameSession::new(2, 2, Box::new(RandomMover::new()), Box::new(RandomMover::new()));
+        full.apply_column(0).unwrap();
+        full.apply_column(0).unwrap();
+        assert_eq!(full.apply_column(0), Err(MoveError::Occupied));
+    }
+
+    #[test]
+    fn game_resumes_from_a_position() {
+        let mut gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,1,0,0],
+                [0,0,0,0,1,0,0],
+                [0,2,2,2,1,0,0]
+            ]
+        );
+        gs.turn = Player::P2;
+        let mut game = Game::play_from_position(gs.clone(), Box::new(MinMaxAgent::new_with_depth(4)), Box::new(MinMaxAgent::new_with_depth(4))).unwrap();
+        assert_eq!(game.start_game(), GameResult::Win(Player::P2));
+        assert_eq!(game.history(), &[Move::new(5, 0)]);
+
+        let won = play(Move::new(5, 0), &gs).unwrap();
+        assert!(matches!(Game::play_from_position(won, Box::new(RandomMover::new()), Box::new(RandomMover::new())), Err(BoardError::Finished)));
+        let floating = GameState::new_from_board(vec2d![[1,0],[0,0]]);
+        assert!(matches!(Game::play_from_position(floating, Box::new(RandomMover::new()), Box::new(RandomMover::new())), Err(BoardError::FloatingDisc(_))));
+        let mut pop_out = GameState::new();
+        pop_out.set_pop_out(true);
+        assert!(matches!(Game::play_from_position(pop_out, Box::new(RandomMover::new()), Box::new(RandomMover::new())), Err(BoardError::PopOut)));
+    }
+
+    /// Thinks for a while, then plays the leftmost column.
+    struct Slow;
+
+    impl Agent for Slow {
+        fn next_move(&mut self, gs: &GameState) -> Move {
+            std::thread::sleep(Duration::from_millis(50));
+            get_legal(gs)[0]
+        }
+    }
+
+    /// Never comes back with a move.
+    struct Hung;
+
+    impl Agent for Hung {
+        fn next_move(&mut self, _gs: &GameState) -> Move {
+            loop {
+                std::thread::park();
+            }
+        }
+    }
+
+    #[test]
+    fn hung_agents_run_out_of_time() {
+        let mut game = Game::new_with_agents(6, 7, Box::new(Hung), Box::new(RandomMover::new()));
+        game.set_move_time_limit(Some(Duration::from_millis(50)), TimeoutPolicy::Forfeit);
+        assert_eq!(game.start_game(), GameResult::Win(Player::P2));
+        assert!(game.history().is_empty());
+
+        let mut session = GameSession::new(6, 7, Box::new(Hung), Box::new(Counter::default()));
+        session.set_move_time_limit(Some(Duration::from_millis(50)), TimeoutPolicy::RandomMove);
+        for _ in 0..4 {
+            assert_eq!(session.agent_move(), Ok(None));
+        }
+        assert_eq!(session.history().len(), 4);
+        assert_eq!((session.history()[1].col(), session.history()[3].col()), (0, 1));
+    }
+
+    #[test]
+    fn slow_agents_run_out_of_time() {
+        let mut game = Game::new_with_agents(6, 7, Box::new(Slow), Box::new(RandomMover::new()));
+        game.set_move_time_limit(Some(Duration::from_millis(10)), TimeoutPolicy::Forfeit);
+        assert_eq!(game.start_game(), GameResult::Win(Player::P2));
+        assert!(game.history().is_empty());
+
+        rng::seed(318);
+        let mut session = GameSession::new(6, 7, Box::new(Slow), Box::new(Slow));
+        session.set_move_time_limit(Some(Duration::from_millis(10)), TimeoutPolicy::RandomMove);
+        for _ in 0..4 {
+            assert_eq!(session.agent_move(), Ok(None));
+        }
+        assert_ne!(session.history().iter().map(|mov| mov.col()).collect::<Vec<_>>(), vec![0, 0, 0, 0]);
+
+        let mut session = GameSession::new(6, 7, Box::new(Slow), Box::new(Slow));
+        session.set_move_time_limit(Some(Duration::from_secs(10)), TimeoutPolicy::Forfeit);
+        assert_eq!(session.agent_move(), Ok(None));
+        assert_eq!(session.history(), &[Move::new(5, 0)]);
+    }
+
+    /// Plays the columns in turn, counting its moves.
+    #[derive(Default)]
+    struct Counter {
+        moves: usize,
+    }
+
+    impl Agent for Counter {
+        fn next_move(&mut self, gs: &GameState) -> Move {
+            self.moves += 1;
+            Move::from_col((self.moves - 1) % gs.cols(), gs).unwrap()
+        }
+    }
+
+    #[test]
+    fn agents_keep_state_between_moves() {
+        let mut counter = Counter::default();
+        let agent: &mut dyn Agent = &mut counter;
+        let gs = GameState::new();
+        for _ in 0..3 {
+            agent.next_move(&gs);
+        }
+        assert_eq!(counter.moves, 3);
+
+        let mut game = Game::new_with_agents(6, 7, Box::new(Counter::default()), Box::new(Counter::default()));
+        for _ in 0..6 {
+            game.next();
+        }
+        assert_eq!(game.history().iter().map(|mov| mov.col()).collect::<Vec<_>>(), vec![0, 0, 1, 1, 2, 2]);
+    }
+
+    #[test]
+    fn sideways_games_play_the_agents_moves() {
+        let mut gs = GameState::new();
+        gs.set_gravity(GravityDir::Left);
+        let mut session = GameSession::from_position(gs.clone(), Box::new(Counter::default()), Box::new(Counter::default())).unwrap();
+        for _ in 0..3 {
+            session.agent_move().unwrap();
+        }
+        assert_eq!(session.history(), &[Move::new(0, 0), Move::new(0, 1), Move::new(1, 0)]);
+
+        let mut game = Game::play_from_position(gs, Box::new(Counter::default()), Box::new(Counter::default())).unwrap();
+        for _ in 0..3 {
+            game.next();
+        }
+        assert_eq!(game.export_moves(), "0 0 1");
+    }
+
+    #[test]
+    fn second_player_can_start() {
+        let mut game = Game::new_with_agents(6, 7, Box::new(Counter::default()), Box::new(Counter::default()));
+        assert_eq!(game.set_first_player(FirstPlayer::Fixed(Player::P2)), Some(Player::P2));
+        game.next();
+        assert_eq!(game.state().disc_at(5, 0), Some(Player::P2));
+        assert_eq!(game.state().turn(), Player::P1);
+        assert_eq!(game.set_first_player(FirstPlayer::Fixed(Player::P1)), None);
+        for _ in 0..4 {
+            game.next();
+        }
+        let moves = game.export_moves();
+        assert_eq!(moves, "o 0 0 1 1 2");
+        assert_eq!(Game::replay(&moves, 6, 7).as_ref(), Ok(game.state()));
+        assert_eq!(Game::replay("x 0 0", 6, 7), Game::replay("0 0", 6, 7));
+
+        let resumed = play_col(3, &GameState::new()).unwrap();
+        let mut session = GameSession::from_position(resumed, Box::new(Counter::default()), Box::new(Counter::default())).unwrap();
+        assert!(!session.set_first_player(Player::P1));
+        assert_eq!(session.current_state().turn(), Player::P2);
+
+        rng::seed(325);
+        let firsts: Vec<Player> = (0..20).map(|_| {
+            let mut game = Game::new_with_agents(6, 7, Box::new(RandomMover::new()), Box::new(RandomMover::new()));
+            game.set_first_player(FirstPlayer::Random).unwrap()
+        }).collect();
+        assert!(firsts.contains(&Player::P1) && firsts.contains(&Player::P2));
+    }
+
+    #[test]
+    fn observer_sees_every_move() {
+        rng::seed(319);
+        let events = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let mut game = Game::new_with_agents(4, 4, Box::new(RandomMover::new()), Box::new(RandomMover::new()));
+        let seen = events.clone();
+        game.set_observer(Box::new(move |event| seen.borrow_mut().push(match event {
+            GameEvent::MoveMade { state, mov } => Ok((mov.col(), state.turn())),
+            GameEvent::GameOver(res) => Err(*res),
+        })));
+        let res = game.start_game();
+        let mut expected: Vec<Result<(usize, Player), GameResult>> = Vec::new();
+        let mut gs = GameState::new_with_size(4, 4);
+        for mov in game.history() {
+            gs = play(*mov, &gs).unwrap();
+            expected.push(Ok((mov.col(), gs.turn())));
+        }
+        expected.push(Err(res));
+        assert_eq!(*events.borrow(), expected);
+    }
+
+    #[test]
+    fn session_plays_a_human_against_an_agent() {
+        rng::seed(298);
+        let mut session = GameSession::new(6, 7, Box::new(RandomMover::new()), Box::new(MinMaxAgent::new_with_depth(3)));
+        let res = loop {
+            let col = get_legal(session.current_state())[0].col();
+            if let Some(res) = session.apply_column(col).unwrap() {
+                break res;
+            }
+            if let Some(res) = session.agent_move().unwrap() {
+                break res;
+            }
+        };
+        assert_eq!(session.result(), Some(res));
+        assert_eq!(result(session.current_state()), Some(res));
+        assert_eq!(Game::replay(&session.history().iter().map(|mov| mov.col().to_string()).collect::<Vec<_>>().join(" "), 6, 7).as_ref(), Ok(session.current_state()));
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn parallel_root_search_matches_sequential() {
+        rng::seed(287);
+        for i in 0..20 {
+            let gs = get_random_position(&GameState::new(), 2 + i % 20);
+            if result(&gs).is_some() {
+                continue;
+            }
+            let (sequential, sequential_score, _) = MinMaxAgent::new_with_depth(5).next_move_with_stats(&gs);
+            let (parallel, parallel_score, _) = MinMaxAgent::new_with_depth(5).with_parallel_root(true).next_move_with_stats(&gs);
+            assert_eq!((parallel, parallel_score), (sequential, sequential_score), "{}", gs);
+        }
+        let mut stuck = GameState::new();
+        (0..stuck.cols()).for_each(|col| stuck.disable_column(col));
+        assert_eq!(MinMaxAgent::new_with_depth(3).search_parallel(&stuck, 3, Vec::new(), &mut SearchStats::default()), None);
+    }
+
+    #[test]
+    fn aspiration_windows_agree_with_full_windows() {
+        rng::seed(288);
+        let (mut plain_nodes, mut aspiration_nodes) = (0, 0);
+        for i in 0..20 {
+            let gs = get_random_position(&GameState::new(), 2 + i % 20);
+            if result(&gs).is_some() {
+                continue;
+            }
+            let (plain, plain_score, plain_stats) = MinMaxAgent::new_with_depth(6).next_move_with_stats(&gs);
+            let (aspiration, aspiration_score, aspiration_stats) = MinMaxAgent::new_with_depth(6).with_aspiration_window(Some(1.0)).next_move_with_stats(&gs);
+            assert_eq!((aspiration, aspiration_score), (plain, plain_score), "{}", gs);
+            plain_nodes += plain_stats.nodes;
+            aspiration_nodes += aspiration_stats.nodes;
+        }
+        assert!(aspiration_nodes < plain_nodes, "{} vs {}", aspiration_nodes, plain_nodes);
+    }
+
+    #[test]
+    fn reports_every_depth() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        let mut agent = MinMaxAgent::new_with_depth(5)
+            .with_depth_callback(Some(Box::new(move |report: &DepthReport| sink.lock().unwrap().push(report.clone()))));
+        assert_eq!(agent.last_search_stats().nodes, 0);
+        let mov = agent.next_move(&GameState::new());
+        let stats = agent.last_search_stats();
+        assert!(stats.nodes > 0);
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.iter().map(|report| report.depth).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+        assert_eq!(reports.last().unwrap().best, mov);
+        assert_eq!(reports.iter().map(|report| report.nodes).sum::<u64>(), stats.nodes);
+        assert!(reports.windows(2).all(|pair| pair[0].nodes < pair[1].nodes));
+    }
+
+    #[test]
+    fn counts_table_hits_and_cutoffs() {
+        let mut shallow = MinMaxAgent::new_with_depth(2);
+        shallow.next_move(&GameState::new());
+        assert_eq!(shallow.last_search_stats().tt_hits, 0);
+        let mut deep = MinMaxAgent::new_with_depth(6);
+        deep.next_move(&GameState::new());
+        let stats = deep.last_search_stats();
+        assert!(stats.tt_hits > 0);
+        assert!(stats.beta_cutoffs > 0);
+        assert_eq!(stats.max_depth_reached, 6);
+        assert!(stats.elapsed > Duration::ZERO);
+    }
+
+    #[test]
+    fn quiescence_sees_past_the_horizon() {
+        // Whatever P1 plays, P2 completes the bottom row next move, but a depth-1 search stops
+        // before P2's reply.
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,1,1,1,0,0,0],
+                [0,2,2,2,0,0,0]
+            ]
+        );
+        assert!(matches!(MinMaxAgent::new_with_depth(1).analyze(&gs).unwrap().1, Score::Heuristic(_)));
+        assert_eq!(MinMaxAgent::new_with_depth(1).with_quiescence(true).analyze(&gs).unwrap().1, Score::MatedIn(1));
+        let plain = MinMaxAgent::new_with_depth(4).analyze(&gs).unwrap().1;
+        assert_eq!(MinMaxAgent::new_with_depth(4).with_quiescence(true).analyze(&gs).unwrap().1, plain);
+    }
+
+    #[test]
+    fn killer_moves_save_nodes() {
+        rng::seed(290);
+        let (mut plain_nodes, mut killer_nodes) = (0, 0);
+        for i in 0..6 {
+            let gs = get_random_position(&GameState::new(), 4 + 2 * i);
+            if result(&gs).is_some() {
+                continue;
+            }
+            let (plain, plain_score, plain_stats) = MinMaxAgent::new_with_depth(8).with_killer_moves(false).next_move_with_stats(&gs);
+            let (killer, killer_score, killer_stats) = MinMaxAgent::new_with_depth(8).next_move_with_stats(&gs);
+            assert_eq!((killer, killer_score), (plain, plain_score), "{}", gs);
+            plain_nodes += plain_stats.nodes;
+            killer_nodes += killer_stats.nodes;
+        }
+        assert!(killer_nodes < plain_nodes, "{} vs {}", killer_nodes, plain_nodes);
+    }
+
+    #[test]
+    fn center_first_saves_nodes() {
+        let (plain, plain_score, plain_stats) = MinMaxAgent::new_with_depth(8).next_move_with_stats(&GameState::new());
+        let (center, center_score, center_stats) = MinMaxAgent::new_with_depth(8).with_center_first(true).next_move_with_stats(&GameState::new());
+        assert_eq!((center, center_score), (plain, plain_score));
+        assert!(center_stats.nodes < plain_stats.nodes, "{} vs {}", center_stats.nodes, plain_stats.nodes);
+    }
+
+    #[test]
+    fn threat_ordering_keeps_the_result() {
+        rng::seed(312);
+        for i in 0..10 {
+            let gs = get_random_position(&GameState::new(), 6 + i);
+            if result(&gs).is_some() {
+                continue;
+            }
+            let (plain, plain_score, _) = MinMaxAgent::new_with_depth(5).next_move_with_stats(&gs);
+            let (ordered, ordered_score, _) = MinMaxAgent::new_with_depth(5).with_threat_ordering(true).next_move_with_stats(&gs);
+            assert_eq!((ordered, ordered_score), (plain, plain_score), "{}", gs);
+        }
+    }
+
+    /// Resigns, or offers and accepts draws, then plays the leftmost column.
+    struct Quitter {
+        action: Action,
+    }
+
+    impl Agent for Quitter {
+        fn next_move(&mut self, gs: &GameState) -> Move {
+            get_legal(gs)[0]
+        }
+
+        fn propose(&mut self, gs: &GameState) -> Action {
+            match self.action {
+                Action::Move(_) => Action::Move(self.next_move(gs)),
+                action => action,
+            }
+        }
+
+        fn accept_draw(&mut self, _gs: &GameState) -> bool {
+            self.action == Action::OfferDraw
+        }
+    }
+
+    /// Plays the top left cell, which is never legal on a fresh board.
+    struct Cheater;
+
+    impl Agent for Cheater {
+        fn next_move(&mut self, _gs: &GameState) -> Move {
+            Move::new(0, 0)
+        }
+    }
+
+    #[test]
+    fn illegal_moves_forfeit_the_game() {
+        let mut game = Game::new_with_agents(6, 7, Box::new(RandomMover::new()), Box::new(Cheater));
+        assert_eq!(game.start_game(), GameResult::Win(Player::P1));
+        assert_eq!(game.history().len(), 1);
+        assert_eq!(game.next(), Some(GameResult::Win(Player::P1)));
+
+        let mut gs = GameState::new_with_size(2, 7);
+        gs.disable_column(3);
+        let mut session = GameSession::from_position(gs, Box::new(Cheater), Box::new(Cheater)).unwrap();
+        assert_eq!(session.apply_column(3), Err(MoveError::ColumnDisabled));
+        session.apply_column(0).unwrap();
+        session.apply_column(0).unwrap();
+        assert_eq!(session.apply_column(0), Err(MoveError::Occupied));
+        assert_eq!(session.agent_move(), Err(MoveError::Occupied));
+    }
+
+    #[test]
+    fn games_without_open_columns_are_drawn() {
+        let mut gs = GameState::new_with_size(2, 3);
+        gs.disable_column(2);
+        let mut game = Game::play_from_position(gs.clone(), Box::new(MinMaxAgent::new_with_depth(4)), Box::new(RandomMover::new())).unwrap();
+        assert_eq!(game.start_game(), GameResult::Draw);
+        assert_eq!(game.history().len(), 4);
+        assert_eq!(result(game.state()), None);
+
+        let stuck = game.state().clone();
+        let mut session = GameSession::from_position(stuck, Box::new(Cheater), Box::new(Cheater)).unwrap();
+        assert_eq!(session.agent_move(), Err(MoveError::NoMoves));
+        assert!(session.history().is_empty());
+    }
+
+    #[test]
+    fn resigning_loses_the_game() {
+        let mut game = Game::new_with_agents(6, 7, Box::new(Quitter { action: Action::Resign }), Box::new(RandomMover::new()));
+        assert_eq!(game.start_game(), GameResult::Win(Player::P2));
+        assert!(game.history().is_empty());
+    }
+
+    #[test]
+    fn draws_need_both_players() {
+        let mut agreed = Game::new_with_agents(6, 7, Box::new(Quitter { action: Action::OfferDraw }), Box::new(Quitter { action: Action::OfferDraw }));
+        assert_eq!(agreed.start_game(), GameResult::Draw);
+        assert!(agreed.history().is_empty());
+        // A declined offer still has to be followed by a move.
+        let mut declined = Game::new_with_agents(6, 7, Box::new(Quitter { action: Action::OfferDraw }), Box::new(Quitter { action: Action::Move(Move::new(0, 0)) }));
+        declined.start_game();
+        assert!(result(declined.state()).is_some());
+        assert_eq!(declined.history()[0], Move::new(5, 0));
+    }
+
+    #[test]
+    fn difficulty_presets() {
+        let presets = [
+            (Difficulty::Easy, 2, None),
+            (Difficulty::Medium, 4, None),
+            (Difficulty::Hard, 7, None),
+            (Difficulty::Expert, 42, Some(Duration::from_secs(10))),
+        ];
+        for (difficulty, depth, time_limit) in presets {
+            let agent = MinMaxAgent::from_difficulty(difficulty, 6, 7);
+            assert_eq!((agent.depth(), agent.time_limit()), (depth, time_limit));
+        }
+    }
+
+    #[test]
+    fn time_limit_stops_deepening() {
+        let mut agent = MinMaxAgent::new_with_depth(42).with_time_limit(Some(Duration::from_millis(100)));
+        let start = std::time::Instant::now();
+        let (mov, _, stats) = agent.next_move_with_stats(&GameState::new());
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(!stats.depths.is_empty() && stats.depths.len() < 42);
+        assert_eq!(stats.depths.last().unwrap().best, mov);
+    }
+
+    #[test]
+    fn later_moves_get_more_time() {
+        let agent = MinMaxAgent::new().with_game_time(Some(Duration::from_secs(60)));
+        let empty = GameState::new();
+        let nearly_full = GameState::from_compact("1xoxoxo/oxoxoxo/oxoxoxo/xoxoxox/oxoxoxo/xoxoxox x").unwrap();
+        assert_eq!(agent.time_budget(&empty), Some(Duration::from_secs(60) / 21));
+        assert_eq!(agent.time_budget(&nearly_full), Some(Duration::from_secs(60)));
+        let mut rows = vec![vec![0; 7]; 5];
+        rows.push(vec![-1, -1, -1, -1, 0, 0, 0]);
+        assert_eq!(agent.time_budget(&GameState::new_from_board(rows)), Some(Duration::from_secs(60) / 19));
+        let capped = agent.with_time_limit(Some(Duration::from_secs(10)));
+        assert_eq!(capped.time_budget(&nearly_full), Some(Duration::from_secs(10)));
+        assert_eq!(MinMaxAgent::new().time_budget(&empty), None);
+    }
+
+    #[test]
+    fn game_clock_runs_down() {
+        let mut agent = MinMaxAgent::new_with_depth(42).with_game_time(Some(Duration::from_millis(2100)));
+        let start = std::time::Instant::now();
+        agent.next_move(&GameState::new());
+        // The move gets a hundred milliseconds, a twenty-first of the clock, but only running past
+        // the whole clock is a sure sign that it was ignored.
+        assert!(start.elapsed() < Duration::from_millis(2100));
+        assert!(agent.time_budget(&GameState::new()).unwrap() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn epsilon_controls_random_moves() {
+        rng::seed(267);
+        let gs = get_random_position(&GameState::new(), 6);
+        let expected = MinMaxAgent::new_with_depth(3).next_move(&gs);
+        for seed in 0..10 {
+            let mut greedy = EpsilonGreedyAgent::new(Box::new(MinMaxAgent::new_with_depth(3)), 0.0, seed);
+            assert_eq!(greedy.next_move(&gs), expected);
+        }
+        let mut cols = std::collections::HashSet::new();
+        for seed in 0..200 {
+            let mut random = EpsilonGreedyAgent::new(Box::new(MinMaxAgent::new_with_depth(3)), 1.0, seed);
+            cols.insert(random.next_move(&gs).col());
+        }
+        assert_eq!(cols.len(), get_legal(&gs).len());
+    }
+
+    #[test]
+    fn seeded_random_movers_repeat_each_other() {
+        rng::seed(309);
+        let positions: Vec<GameState> = (0..100).map(|i| get_random_position(&GameState::new(), i % 40)).collect();
+        let mut first = RandomMover::new_seeded(7);
+        let mut second = RandomMover::new_seeded(7);
+        let first_moves: Vec<Move> = positions.iter().map(|gs| first.next_move(gs)).collect();
+        rng::seed(0);
+        let second_moves: Vec<Move> = positions.iter().map(|gs| second.next_move(gs)).collect();
+        assert_eq!(first_moves, second_moves);
+        let mut other = RandomMover::new_seeded(8);
+        assert_ne!(positions.iter().map(|gs| other.next_move(gs)).collect::<Vec<_>>(), first_moves);
+    }
+
+    /// Only cares about discs in the leftmost column.
+    struct LeftEdgeEvaluator;
+
+    impl Evaluator for LeftEdgeEvaluator {
+        fn evaluate(&self, gs: &GameState) -> f32 {
+            (0..gs.rows()).map(|row| match gs.disc_at(row, 0) {
+                Some(Player::P1) => 1.0,
+                Some(Player::P2) => -1.0,
+                None => 0.0,
+            }).sum()
+        }
+    }
+
+    #[test]
+    fn labels_tell_settings_apart() {
+        let labels = [
+            MinMaxAgent::new_with_depth(4).label(),
+            MinMaxAgent::new_with_depth(4).with_evaluator(Box::new(LeftEdgeEvaluator)).label(),
+            MinMaxAgent::new_with_depth(4).with_evaluator(Box::new(WeightedWindowEvaluator::default())).label(),
+            MinMaxAgent::new_with_depth(4).with_time_limit(Some(Duration::from_millis(500))).label(),
+        ];
+        assert_eq!(labels[0], "minmax-d4-window");
+        assert_eq!(labels[2], "minmax-d4-weighted(0,1,10,100)");
+        assert_eq!(labels[3], "minmax-d4-window-t500ms");
+        for (i, label) in labels.iter().enumerate() {
+            assert!(!labels[..i].contains(label), "{}", label);
+        }
+    }
+
+    #[test]
+    fn evaluator_is_pluggable() {
+        let gs = GameState::new();
+        assert_eq!(MinMaxAgent::new_with_depth(1).next_move(&gs), Move::new(5, 3));
+        let mut edge = MinMaxAgent::new_with_depth(1).with_evaluator(Box::new(LeftEdgeEvaluator));
+        assert_eq!(edge.next_move(&gs), Move::new(5, 0));
+    }
+
+    /// Scores every position the same, so that every move ties.
+    struct FlatEvaluator;
+
+    impl Evaluator for FlatEvaluator {
+        fn evaluate(&self, _gs: &GameState) -> f32 {
+            0.0
+        }
+    }
+
+    #[test]
+    fn ties_go_to_the_center() {
+        for (cols, col) in [(7, 3), (6, 2)] {
+            let gs = GameState::new_with_size(6, cols);
+            let mut agent = MinMaxAgent::new_with_depth(3).with_evaluator(Box::new(FlatEvaluator)).with_symmetry_pruning(false);
+            for _ in 0..3 {
+                assert_eq!(agent.next_move(&gs).col(), col);
+            }
+        }
+        let gs = play_col(3, &GameState::new()).unwrap();
+        assert_eq!(MinMaxAgent::new_with_depth(2).with_evaluator(Box::new(FlatEvaluator)).next_move(&gs).col(), 3);
+    }
+
+    #[test]
+    fn prefers_center_column_on_empty_board() {
+        let gs = GameState::new();
+        // Depth 2 scores columns 2 to 4 the same, and the tie goes to the center.
+        let scores = MinMaxAgent::new_with_depth(2).analyze_all(&gs);
+        assert_eq!(scores[2].1, scores[3].1);
+        for depth in 1..=6 {
+            assert_eq!(MinMaxAgent::new_with_depth(depth).next_move(&gs).col(), 3, "depth {}", depth);
+        }
     }
 }
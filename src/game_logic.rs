@@ -1,9 +1,16 @@
-use std::borrow::Borrow;
-use std::cmp::min;
+use std::collections::HashSet;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::fmt::Formatter;
 use std::ops::Add;
+use std::sync::OnceLock;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use crate::rng::with_rng;
 
+#[cfg(test)]
 macro_rules!vec2d {
     [ $( [ $( $d:expr ),* ] ),* ] => {
         vec![
@@ -14,249 +21,1554 @@ macro_rules!vec2d {
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
 pub enum Player{
     P1,
     P2
 }
 
+/// Whether `Display` output is colored: it is unless the `NO_COLOR` environment variable is set.
+pub fn colors_enabled() -> bool {
+    std::env::var_os("NO_COLOR").is_none()
+}
+
+/// Wraps `text` in the ANSI escape codes for `color`, or leaves it plain.
+fn paint(text : &str, color : u8, colored : bool) -> String {
+    if colored {
+        format!("\u{001b}[{}m{}\u{001b}[0m", color, text)
+    } else {
+        text.to_string()
+    }
+}
+
+impl Player {
+    /// The player's name, in the colour of their discs unless `colored` is false.
+    pub fn symbol(&self, colored : bool) -> String {
+        match self {
+            Player::P1 => paint("P1", 31, colored),
+            Player::P2 => paint("P2", 33, colored),
+        }
+    }
+}
+
 impl fmt::Display for Player {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let string  =
-            match self{
-                (Player::P1) => "\u{001b}[31mP1\u{001b}[0m",
-                (Player::P2) => "\u{001b}[33mP2\u{001b}[0m",
-                _ => {""}
-            };
-        write!(f, "{}", string)
+        write!(f, "{}", self.symbol(colors_enabled()))
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
 pub enum GameResult {
     Win(Player),
     Draw
 }
 
+impl GameResult {
+    pub fn render(&self, colored : bool) -> String {
+        match self{
+            GameResult::Draw => paint("Draw", 34, colored),
+            GameResult::Win(player) => player.symbol(colored)
+        }
+    }
+
+    pub fn winner(&self) -> Option<Player> {
+        match self {
+            GameResult::Win(player) => Some(*player),
+            GameResult::Draw => None,
+        }
+    }
+
+    /// What the result is worth to `player`: 1 for a win, 0 for a loss and a half for a draw.
+    pub fn score_for(&self, player : Player) -> f32 {
+        match self.winner() {
+            Some(winner) if winner == player => 1.0,
+            Some(_) => 0.0,
+            None => 0.5,
+        }
+    }
+}
+
 impl fmt::Display for GameResult {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let string  =
-            match self{
-                GameResult::Draw => "\u{001b}[34mDraw\u{001b}[0m".to_string(),
-                GameResult::Win(player) => format!("{}", player)
-            };
-        write!(f, "{}", string)
+        write!(f, "{}", self.render(colors_enabled()))
     }
 }
 
-type Disc = Option<Player>;
+/// What a cell holds: a player's disc, or `None` when it is empty.
+pub type Disc = Option<Player>;
 
-#[derive(Copy, Clone, Debug)]
+/// The side of the board discs fall towards. With `Down` or `Up` a disc is dropped into a column,
+/// with `Left` or `Right` into a row, and either way it comes to rest on the edge, a disc or a
+/// blocked cell.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum GravityDir {
+    #[default]
+    Down,
+    Up,
+    Left,
+    Right,
+}
+
+/// The `new_from_board` cell value of a blocked cell.
+const BLOCKED : i8 = -1;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub struct Move {
     row : usize,
     col : usize
 }
 
-#[derive(Clone)]
+impl Move {
+    pub fn new(row : usize, col : usize) -> Self {
+        Self {
+            row,
+            col,
+        }
+    }
+
+    /// The landing cell for a disc dropped in `col`, or `None` if the column is full, disabled or
+    /// off the board. Under sideways gravity `col` is the row the disc is slid into.
+    pub fn drop(gs : &GameState, col : usize) -> Option<Move> {
+        if col >= gs.lanes() {
+            return None;
+        }
+        legal_in_col(gs, col)
+    }
+
+    /// `Move::drop` with the arguments in the order of `play_col`.
+    pub fn from_col(col : usize, gs : &GameState) -> Option<Move> {
+        Self::drop(gs, col)
+    }
+
+    pub fn row(&self) -> usize {
+        self.row
+    }
+
+    pub fn col(&self) -> usize {
+        self.col
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct GameState {
     pub(crate) turn: Player,
     board: Vec<Vec<Disc>>,
     rows: usize,
-    cols: usize
+    pub(crate) cols: usize,
+    /// How many discs in a line win the game.
+    win_len: usize,
+    /// Discs and blocked cells stacked in each column, kept in step with `board` so finding where
+    /// a disc lands does not need to scan the column.
+    heights: Vec<usize>,
+    /// Columns nobody may drop in for now, for puzzles. Unlike the board this can change mid-game.
+    disabled_columns: HashSet<usize>,
+    /// Cells no disc can ever occupy, for variants with blocked squares or non-rectangular boards.
+    /// A dropped disc passes over them to the next free cell, and they break every line through them.
+    blocked: HashSet<Move>,
+    /// The Pop Out rule: instead of dropping a disc, a player may pull one of their own discs out
+    /// of the bottom row, and the rest of the column slides down.
+    pop_out: bool,
+    gravity: GravityDir,
+    /// The Zobrist keys of the discs on the board and on its mirror image, updated with every disc
+    /// that is added or removed rather than recomputed from the whole board.
+    disc_keys: u64,
+    mirror_disc_keys: u64,
+    /// Discs on the board, kept up to date like the Zobrist keys.
+    placed: usize,
+}
+
+/// Zobrist keys are drawn for a square of this many cells a side. Larger boards reuse them, which
+/// only makes collisions more likely, and the transposition table checks for those anyway.
+const ZOBRIST_SIDE : usize = 64;
+const ZOBRIST_SEED : u64 = 307;
+const ZOBRIST_TURN : u64 = 0x9e37_79b9_7f4a_7c15;
+const ZOBRIST_RULES : u64 = 0xbf58_476d_1ce4_e5b9;
+
+/// Keys for a P1 disc, a P2 disc and a blocked cell on each cell.
+fn zobrist_keys() -> &'static [[u64; 3]] {
+    static KEYS: OnceLock<Vec<[u64; 3]>> = OnceLock::new();
+    KEYS.get_or_init(|| {
+        let mut rng = ChaCha8Rng::seed_from_u64(ZOBRIST_SEED);
+        (0..ZOBRIST_SIDE * ZOBRIST_SIDE + ZOBRIST_SIDE).map(|_| [rng.gen(), rng.gen(), rng.gen()]).collect()
+    })
+}
+
+fn zobrist_index(row : usize, col : usize) -> usize {
+    (row % ZOBRIST_SIDE) * ZOBRIST_SIDE + col % ZOBRIST_SIDE
+}
+
+fn zobrist_key(row : usize, col : usize, player : Player) -> u64 {
+    zobrist_keys()[zobrist_index(row, col)][player as usize]
+}
+
+fn zobrist_blocked_key(row : usize, col : usize) -> u64 {
+    zobrist_keys()[zobrist_index(row, col)][2]
+}
+
+/// Keys for disabled lanes, drawn after the cell keys.
+fn zobrist_lane_key(lane : usize) -> u64 {
+    zobrist_keys()[ZOBRIST_SIDE * ZOBRIST_SIDE + lane % ZOBRIST_SIDE][0]
+}
+
+impl Hash for GameState {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.turn.hash(state);
+        self.disc_keys.hash(state);
+        self.rows.hash(state);
+        self.cols.hash(state);
+        self.win_len.hash(state);
+        let mut disabled : Vec<&usize> = self.disabled_columns.iter().collect();
+        disabled.sort();
+        disabled.hash(state);
+        let mut blocked : Vec<(usize, usize)> = self.blocked.iter().map(|mov| (mov.row, mov.col)).collect();
+        blocked.sort();
+        blocked.hash(state);
+        self.pop_out.hash(state);
+        self.gravity.hash(state);
+    }
+}
+
+impl Default for GameState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl GameState {
     pub fn new() -> Self {
+        Self::new_with_size(6, 7)
+    }
+
+    pub fn new_with_size(rows : usize, cols : usize) -> Self {
+        Self::new_with_win_len(rows, cols, DEFAULT_WIN_LEN)
+    }
+
+    /// An empty board that `turn` moves first on.
+    pub fn new_with_turn(rows : usize, cols : usize, turn : Player) -> Self {
+        Self {
+            turn,
+            ..Self::new_with_size(rows, cols)
+        }
+    }
+
+    /// A connect-`win_len` board, for variants like connect-3 or connect-5.
+    pub fn new_with_win_len(rows : usize, cols : usize, win_len : usize) -> Self {
         Self {
             turn : Player::P1,
-            board : vec![vec![None ; 7] ; 6],
-            rows : 6,
-            cols : 7,
+            board : vec![vec![None ; cols] ; rows],
+            rows,
+            cols,
+            win_len,
+            heights : vec![0; cols],
+            disabled_columns : HashSet::new(),
+            blocked : HashSet::new(),
+            pop_out : false,
+            gravity : GravityDir::Down,
+            disc_keys : 0,
+            mirror_disc_keys : 0,
+            placed : 0,
         }
     }
+
+    /// A board from rows of cells from the top down: 1 for P1, 2 for P2, -1 for a blocked cell and
+    /// anything else for an empty one.
     pub fn new_from_board(raw_board: Vec<Vec<i8>>) -> Self {
         let board : Vec<Vec<Disc>>  = raw_board.iter().map(|row| row.iter().map(|n| match n {
             1 => Some(Player::P1),
             2 => Some(Player::P2),
             _ => None
         }).collect()).collect();
+        let blocked : HashSet<Move> = raw_board.iter().enumerate()
+            .flat_map(|(row, cells)| cells.iter().enumerate().filter(|(_, cell)| **cell == BLOCKED).map(move |(col, _)| Move::new(row, col)))
+            .collect();
         Self {
             turn : Player::P1,
+            rows : board.len(),
+            cols : board[0].len(),
+            heights : column_heights(&board, &blocked),
             board,
-            rows : 6,
-            cols : 7,
+            win_len : DEFAULT_WIN_LEN,
+            disabled_columns : HashSet::new(),
+            blocked,
+            pop_out : false,
+            gravity : GravityDir::Down,
+            disc_keys : 0,
+            mirror_disc_keys : 0,
+            placed : 0,
+        }.refreshed()
+    }
+
+    /// Like `new_from_board`, but rejects boards that cannot come up in a game. The side to move
+    /// follows from the disc counts.
+    pub fn try_from_board(raw_board: Vec<Vec<i8>>) -> Result<Self, BoardError> {
+        let cols = raw_board.first().map_or(0, |row| row.len());
+        if cols == 0 {
+            return Err(BoardError::Empty);
+        }
+        if raw_board.iter().any(|row| row.len() != cols) {
+            return Err(BoardError::NotRectangular);
+        }
+        for (row, cells) in raw_board.iter().enumerate() {
+            for (col, cell) in cells.iter().enumerate() {
+                if !(BLOCKED..=2).contains(cell) {
+                    return Err(BoardError::UnknownCell(*cell));
+                }
+                if *cell > 0 && raw_board[row + 1..].iter().any(|below| below[col] == 0) {
+                    return Err(BoardError::FloatingDisc(Move::new(row, col)));
+                }
+            }
+        }
+        let count = |player| raw_board.iter().flatten().filter(|cell| **cell == player).count();
+        let (p1, p2) = (count(1), count(2));
+        if p1.abs_diff(p2) > 1 {
+            return Err(BoardError::DiscCounts(p1, p2));
+        }
+        let mut gs = Self::new_from_board(raw_board);
+        gs.turn = if p1 > p2 { Player::P2 } else { Player::P1 };
+        Ok(gs)
+    }
+
+    /// Checks that a position built by hand can be played on: no disc floats over an empty cell,
+    /// the disc counts could come from alternating moves (pops aside) and the game is not over.
+    /// The side to move is taken as it is.
+    pub fn validate(&self) -> Result<(), BoardError> {
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                let mov = Move::new(row, col);
+                if self.board[row][col].is_some() && !is_supported(self, mov) {
+                    return Err(BoardError::FloatingDisc(mov));
+                }
+            }
+        }
+        let count = |player| self.board.iter().flatten().filter(|disc| **disc == Some(player)).count();
+        let (p1, p2) = (count(Player::P1), count(Player::P2));
+        if !self.pop_out && p1.abs_diff(p2) > 1 {
+            return Err(BoardError::DiscCounts(p1, p2));
+        }
+        if result(self).is_some() {
+            return Err(BoardError::Finished);
+        }
+        Ok(())
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn win_len(&self) -> usize {
+        self.win_len
+    }
+
+    pub fn turn(&self) -> Player {
+        self.turn
+    }
+
+    /// The number of discs on the board, as `disc_count` counts them.
+    pub fn placed_count(&self) -> usize {
+        disc_count(self)
+    }
+
+    pub fn disc_at(&self, row : usize, col : usize) -> Disc {
+        self.board[row][col]
+    }
+
+    /// Every cell with what it holds, row by row from the top left.
+    pub fn cells(&self) -> impl Iterator<Item = (Move, Disc)> + '_ {
+        self.board.iter().enumerate()
+            .flat_map(|(row, cells)| cells.iter().enumerate().map(move |(col, disc)| (Move::new(row, col), *disc)))
+    }
+
+    pub fn disable_column(&mut self, col : usize) {
+        self.disabled_columns.insert(col);
+    }
+
+    pub fn enable_column(&mut self, col : usize) {
+        self.disabled_columns.remove(&col);
+    }
+
+    pub fn is_column_disabled(&self, col : usize) -> bool {
+        self.disabled_columns.contains(&col)
+    }
+
+    pub fn is_blocked(&self, row : usize, col : usize) -> bool {
+        !self.blocked.is_empty() && self.blocked.contains(&Move::new(row, col))
+    }
+
+    pub fn has_blocked_cells(&self) -> bool {
+        !self.blocked.is_empty()
+    }
+
+    /// How many cells no disc can ever occupy.
+    pub fn blocked_count(&self) -> usize {
+        self.blocked.len()
+    }
+
+    /// Makes discs fall towards another side of the board. Positions already on the board stay
+    /// where they are.
+    pub fn set_gravity(&mut self, gravity : GravityDir) {
+        self.gravity = gravity;
+        self.heights = if gravity == GravityDir::Down { column_heights(&self.board, &self.blocked) } else { vec![0; self.cols] };
+    }
+
+    pub fn gravity(&self) -> GravityDir {
+        self.gravity
+    }
+
+    /// The number of columns a disc can be dropped into, or of rows under sideways gravity.
+    pub fn lanes(&self) -> usize {
+        match self.gravity {
+            GravityDir::Down | GravityDir::Up => self.cols,
+            GravityDir::Left | GravityDir::Right => self.rows,
+        }
+    }
+
+    /// Turns the Pop Out rule on or off. Boards with blocked cells do not support it. Pops are
+    /// only made through `try_pop`, `try_play_ply` and `get_legal_plies`, see `Ply`.
+    pub fn set_pop_out(&mut self, pop_out : bool) {
+        self.pop_out = pop_out;
+    }
+
+    pub fn pop_out(&self) -> bool {
+        self.pop_out
+    }
+
+    fn has_vertical_gravity(&self) -> bool {
+        matches!(self.gravity, GravityDir::Down | GravityDir::Up)
+    }
+
+    /// The lane a disc comes to rest at `mov` through: its column, or its row under sideways
+    /// gravity.
+    pub fn lane_of(&self, mov : Move) -> usize {
+        if self.has_vertical_gravity() { mov.col } else { mov.row }
+    }
+
+    /// A Zobrist hash of the position, kept up to date as discs are played, so it costs nothing to
+    /// read. The rules count towards it too, so the same discs under other rules hash differently.
+    pub fn zobrist(&self) -> u64 {
+        self.disc_keys ^ self.turn_key() ^ self.rules_key(false)
+    }
+
+    /// Keys for the rules, or for their mirror image when `mirror` is set.
+    fn rules_key(&self, mirror : bool) -> u64 {
+        let disabled = disabled_lanes(self, mirror).fold(0, |key, lane| key ^ zobrist_lane_key(lane));
+        let blocked = self.blocked.iter()
+            .fold(0, |key, mov| key ^ zobrist_blocked_key(mov.row, if mirror { self.cols - 1 - mov.col } else { mov.col }));
+        disabled ^ blocked ^ rules_word(self).wrapping_mul(ZOBRIST_RULES)
+    }
+
+    fn turn_key(&self) -> u64 {
+        if self.turn == Player::P2 { ZOBRIST_TURN } else { 0 }
+    }
+
+    /// The Zobrist hash and packed board of `canonical()`, worked out without building it. Searches
+    /// key their tables on this at every node, where cloning the board would be the main cost.
+    pub fn canonical_key(&self) -> (u64, Vec<u64>) {
+        let mut packed = pack(self, false);
+        // The mirror is compared word by word and only written out when it is the one kept.
+        if self.has_vertical_gravity() && packed_words(self, true).lt(packed.iter().copied()) {
+            packed.clear();
+            packed.extend(packed_words(self, true));
+            return (self.mirror_disc_keys ^ self.turn_key() ^ self.rules_key(true), packed);
+        }
+        (self.zobrist(), packed)
+    }
+
+    /// Adds or removes `player`'s disc at `mov` from the Zobrist keys.
+    fn toggle_key(&mut self, mov : Move, player : Player) {
+        self.disc_keys ^= zobrist_key(mov.row, mov.col, player);
+        self.mirror_disc_keys ^= zobrist_key(mov.row, self.cols - 1 - mov.col, player);
+    }
+
+    /// Puts `player`'s disc on the empty cell `mov`.
+    fn place(&mut self, mov : Move, player : Player) {
+        self.board[mov.row][mov.col] = Some(player);
+        self.toggle_key(mov, player);
+        self.placed += 1;
+    }
+
+    /// Empties the cell `mov`.
+    fn remove(&mut self, mov : Move) {
+        if let Some(player) = self.board[mov.row][mov.col].take() {
+            self.toggle_key(mov, player);
+            self.placed -= 1;
+        }
+    }
+
+    /// Computes the Zobrist keys and the disc count from the whole board, for boards built other
+    /// than by playing.
+    fn refreshed(mut self) -> Self {
+        self.disc_keys = 0;
+        self.mirror_disc_keys = 0;
+        self.placed = 0;
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                if let Some(player) = self.board[row][col] {
+                    self.toggle_key(Move::new(row, col), player);
+                    self.placed += 1;
+                }
+            }
+        }
+        self
+    }
+
+    /// The position reflected left to right.
+    pub fn mirrored(&self) -> GameState {
+        let mut mirror = self.clone();
+        for row in mirror.board.iter_mut() {
+            row.reverse();
+        }
+        mirror.heights.reverse();
+        mirror.disabled_columns = self.disabled_columns.iter().map(|col| self.cols - 1 - col).collect();
+        mirror.blocked = self.blocked.iter().map(|mov| Move::new(mov.row, self.cols - 1 - mov.col)).collect();
+        std::mem::swap(&mut mirror.disc_keys, &mut mirror.mirror_disc_keys);
+        mirror
+    }
+
+    /// Whichever of the position and its mirror packs smaller. A position and its mirror share a
+    /// canonical form, and so share search results. Under sideways gravity the mirror is a
+    /// different game, so a position is its own canonical form.
+    pub fn canonical(&self) -> GameState {
+        if !self.has_vertical_gravity() {
+            return self.clone();
         }
+        if pack(self, true) < pack(self, false) { self.mirrored() } else { self.clone() }
     }
 }
 
-impl fmt::Display for GameState {
+const UCI_ROWS : usize = 6;
+const UCI_COLS : usize = 7;
+
+/// Imports a row of the UCI Connect-4 dataset: 42 `x`/`o`/`b` cells ordered column by column
+/// from the bottom up (a1..a6, b1..b6, ...), optionally followed by the outcome label. The dataset
+/// only holds standard Connect-4, so the position has the default rules.
+pub fn from_uci_dataset_row(fields : &[&str]) -> Result<GameState, String> {
+    let cells = UCI_ROWS * UCI_COLS;
+    match fields.len() {
+        n if n == cells => {}
+        n if n == cells + 1 && ["win", "loss", "draw"].contains(&fields[cells].trim()) => {}
+        n => return Err(format!("expected {} cells and an optional outcome, got {} fields", cells, n))
+    }
+    let mut board = vec![vec![None; UCI_COLS]; UCI_ROWS];
+    for (i, field) in fields[..cells].iter().enumerate() {
+        board[UCI_ROWS - 1 - i % UCI_ROWS][i / UCI_ROWS] = match field.trim() {
+            "x" => Some(Player::P1),
+            "o" => Some(Player::P2),
+            "b" => None,
+            other => return Err(format!("unknown cell value '{}' at field {}", other, i))
+        };
+    }
+    let count = |player| board.iter().flatten().filter(|disc| **disc == Some(player)).count();
+    let turn = match (count(Player::P1), count(Player::P2)) {
+        (x, o) if x == o => Player::P1,
+        (x, o) if x == o + 1 => Player::P2,
+        (x, o) => return Err(format!("impossible disc counts: {} x and {} o", x, o))
+    };
+    Ok(GameState {
+        turn,
+        heights : column_heights(&board, &HashSet::new()),
+        board,
+        rows : UCI_ROWS,
+        cols : UCI_COLS,
+        win_len : DEFAULT_WIN_LEN,
+        disabled_columns : HashSet::new(),
+        blocked : HashSet::new(),
+        pop_out : false,
+        gravity : GravityDir::Down,
+        disc_keys : 0,
+        mirror_disc_keys : 0,
+        placed : 0,
+    }.refreshed())
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BoardError {
+    Empty,
+    NotRectangular,
+    UnknownCell(i8),
+    FloatingDisc(Move),
+    /// P1 and P2 disc counts that no sequence of alternating moves produces.
+    DiscCounts(usize, usize),
+    /// Someone has already won, or the board is full.
+    Finished,
+    /// Pop Out is on, which game sessions cannot play since agents only drop discs.
+    PopOut,
+}
+
+impl fmt::Display for BoardError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BoardError::Empty => write!(f, "the board is empty"),
+            BoardError::NotRectangular => write!(f, "the rows differ in length"),
+            BoardError::UnknownCell(cell) => write!(f, "unknown cell value {}", cell),
+            BoardError::FloatingDisc(mov) => write!(f, "the disc at {:?} has nothing below it", mov),
+            BoardError::DiscCounts(p1, p2) => write!(f, "impossible disc counts: {} for P1 and {} for P2", p1, p2),
+            BoardError::Finished => write!(f, "the game is already over"),
+            BoardError::PopOut => write!(f, "agents cannot play Pop Out"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseError(pub String);
+
+impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let string : String = "+".to_string().add(&"-".repeat(self.cols)).add("+").add("\n|") + &self.board.iter()
-            .map(|row| row.iter().map(|disc|
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The compact notation's names for gravity other than `Down`.
+const GRAVITY_NAMES : [(GravityDir, &str); 3] = [(GravityDir::Up, "up"), (GravityDir::Left, "left"), (GravityDir::Right, "right")];
+
+/// The widest row `from_compact` reads. Later rows are held to the width of the first, and this
+/// keeps the first from claiming more empty cells than there is memory for.
+const MAX_COMPACT_WIDTH : usize = 1024;
+
+impl GameState {
+    /// A compact one-line notation for sharing positions: rows from the top separated by `/`,
+    /// `x` and `o` for P1 and P2 discs, `#` for a blocked cell, a number for each run of empty
+    /// cells, then the side to move, e.g. `7/7/7/7/7/3x3 o`. Rules other than the defaults follow:
+    /// `w3` for the win length, `up`, `left` or `right` for the gravity, `pop` for Pop Out and
+    /// `d0,6` for the disabled columns, or rows under sideways gravity.
+    pub fn to_string_compact(&self) -> String {
+        let rows : Vec<String> = self.board.iter().enumerate().map(|(row, discs)| {
+            let mut compact = String::new();
+            let mut empty = 0;
+            for (col, disc) in discs.iter().enumerate() {
+                let cell = match disc {
+                    None if self.is_blocked(row, col) => '#',
+                    None => {
+                        empty += 1;
+                        continue;
+                    }
+                    Some(Player::P1) => 'x',
+                    Some(Player::P2) => 'o',
+                };
+                if empty > 0 {
+                    compact += &empty.to_string();
+                    empty = 0;
+                }
+                compact.push(cell);
+            }
+            if empty > 0 {
+                compact += &empty.to_string();
+            }
+            compact
+        }).collect();
+        let mut fields = vec![rows.join("/"), (if self.turn == Player::P1 { "x" } else { "o" }).to_string()];
+        if self.win_len != DEFAULT_WIN_LEN {
+            fields.push(format!("w{}", self.win_len));
+        }
+        if let Some((_, name)) = GRAVITY_NAMES.iter().find(|(gravity, _)| *gravity == self.gravity) {
+            fields.push(name.to_string());
+        }
+        if self.pop_out {
+            fields.push("pop".to_string());
+        }
+        let mut disabled : Vec<usize> = disabled_lanes(self, false).collect();
+        if !disabled.is_empty() {
+            disabled.sort();
+            fields.push(format!("d{}", disabled.iter().map(usize::to_string).collect::<Vec<_>>().join(",")));
+        }
+        fields.join(" ")
+    }
+
+    pub fn from_compact(compact : &str) -> Result<GameState, ParseError> {
+        let error = |message : String| Err(ParseError(message));
+        let mut fields = compact.split_whitespace();
+        let rows = fields.next().unwrap_or_default();
+        let turn = match fields.next() {
+            Some("x") => Player::P1,
+            Some("o") => Player::P2,
+            Some(turn) => return error(format!("unknown side to move '{}'", turn)),
+            None => return error("missing side to move".to_string()),
+        };
+        let (mut win_len, mut gravity, mut pop_out, mut disabled) = (DEFAULT_WIN_LEN, GravityDir::Down, false, HashSet::new());
+        for field in fields {
+            if let Some((named, _)) = GRAVITY_NAMES.iter().find(|(_, name)| *name == field) {
+                gravity = *named;
+            } else if field == "pop" {
+                pop_out = true;
+            } else if let Some(len) = field.strip_prefix('w') {
+                win_len = match len.parse() {
+                    Ok(len) if len > 0 => len,
+                    _ => return error(format!("bad win length '{}'", len)),
+                };
+            } else if let Some(lanes) = field.strip_prefix('d') {
+                for lane in lanes.split(',') {
+                    match lane.parse() {
+                        Ok(lane) => disabled.insert(lane),
+                        Err(_) => return error(format!("bad disabled column '{}'", lane)),
+                    };
+                }
+            } else {
+                return error(format!("unknown field '{}'", field));
+            }
+        }
+        let mut board : Vec<Vec<Disc>> = Vec::new();
+        let mut blocked = HashSet::new();
+        for row in rows.split('/') {
+            let width = board.first().map_or(MAX_COMPACT_WIDTH, Vec::len);
+            let mut cells = Vec::new();
+            let mut empty = String::new();
+            for c in row.chars().chain([' ']) {
+                if c.is_ascii_digit() {
+                    empty.push(c);
+                    continue;
+                }
+                if !empty.is_empty() {
+                    match empty.parse::<usize>() {
+                        Ok(n) if n > width.saturating_sub(cells.len()) => return error(format!("row '{}' does not match the board width", row)),
+                        Ok(n) if n > 0 => cells.extend(std::iter::repeat_n(None, n)),
+                        _ => return error(format!("bad empty run '{}'", empty)),
+                    }
+                    empty.clear();
+                }
+                match c {
+                    'x' => cells.push(Some(Player::P1)),
+                    'o' => cells.push(Some(Player::P2)),
+                    '#' => {
+                        blocked.insert(Move::new(board.len(), cells.len()));
+                        cells.push(None);
+                    }
+                    ' ' => {}
+                    other => return error(format!("unknown cell '{}'", other)),
+                }
+            }
+            if cells.is_empty() || board.first().is_some_and(|first| first.len() != cells.len()) {
+                return error(format!("row '{}' does not match the board width", row));
+            }
+            board.push(cells);
+        }
+        let mut gs = GameState::new_with_win_len(board.len(), board[0].len(), win_len);
+        gs.board = board;
+        gs.blocked = blocked;
+        gs.set_gravity(gravity);
+        if let Some(lane) = disabled.iter().find(|&&lane| lane >= gs.lanes()) {
+            return error(format!("disabled column {} is off the board", lane));
+        }
+        if pop_out && gs.has_blocked_cells() {
+            return error("Pop Out is not played with blocked cells".to_string());
+        }
+        gs.disabled_columns = disabled;
+        gs.pop_out = pop_out;
+        gs.turn = turn;
+        let gs = gs.refreshed();
+        if let Some((mov, _)) = gs.cells().find(|&(mov, disc)| disc.is_some() && !is_supported(&gs, mov)) {
+            return error(format!("floating disc at row {}, column {}", mov.row, mov.col));
+        }
+        Ok(gs)
+    }
+
+    /// Parses a board diagram, one line per row from the top: `.` for an empty cell, `X` or `1`
+    /// for P1, `O` or `2` for P2 and `#` for a blocked cell. The borders and column numbers of `render` are skipped, and a
+    /// colored `O` belongs to whoever's color it has, so `Display` output parses back. The side to
+    /// move follows from the disc counts.
+    pub fn from_ascii(diagram : &str) -> Result<GameState, ParseError> {
+        let mut raw_board : Vec<Vec<i8>> = Vec::new();
+        for line in diagram.lines().map(str::trim) {
+            let is_header = !line.is_empty() && line.chars().enumerate()
+                .all(|(col, c)| c.to_digit(10) == Some((col % 10) as u32));
+            if line.is_empty() || line.starts_with('+') || is_header {
+                continue;
+            }
+            let mut cells = Vec::new();
+            let mut color = 0;
+            let mut chars = line.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\u{001b}' => {
+                        let code : String = chars.by_ref().skip(1).take_while(|c| *c != 'm').collect();
+                        color = code.parse().unwrap_or(0);
+                    }
+                    '|' | ' ' => {}
+                    '.' => cells.push(0),
+                    '#' => cells.push(BLOCKED),
+                    'X' | 'x' | '1' => cells.push(1),
+                    'O' | 'o' if color == 31 => cells.push(1),
+                    'O' | 'o' | '2' => cells.push(2),
+                    other => return Err(ParseError(format!("unknown cell '{}'", other))),
+                }
+            }
+            if raw_board.first().is_some_and(|first| first.len() != cells.len()) {
+                return Err(ParseError(format!("row {} has {} cells, expected {}", raw_board.len(), cells.len(), raw_board[0].len())));
+            }
+            raw_board.push(cells);
+        }
+        GameState::try_from_board(raw_board).map_err(|err| ParseError(err.to_string()))
+    }
+}
+
+impl GameState {
+    /// The board as drawn by `Display`, with or without colors. A header numbers the columns, with
+    /// only the last digit shown from column ten on so that the numbers stay above their columns.
+    pub fn render(&self, colored : bool) -> String {
+        let header : String = (0..self.cols).map(|col| char::from_digit((col % 10) as u32, 10).unwrap()).collect();
+        " ".to_string().add(&header).add("\n+").add(&"-".repeat(self.cols)).add("+").add("\n|") + &self.board.iter().enumerate()
+            .map(|(row, discs)| discs.iter().enumerate().map(|(col, disc)|
             match disc{
-                None => "\u{001b}[34m.\u{001b}[0m",
-                Some(Player::P1) => "\u{001b}[31mO\u{001b}[0m",
-                Some(Player::P2) => "\u{001b}[33mO\u{001b}[0m"
+                None if self.is_blocked(row, col) => "#".to_string(),
+                None => paint(".", 34, colored),
+                Some(Player::P1) => paint("O", 31, colored),
+                Some(Player::P2) => paint("O", 33, colored)
             }
-        ).collect::<Vec<&str>>().join("")).collect::<Vec<String>>().join("|\n|").add("|\n").add("+").add(&"-".repeat(self.cols).add("+").add("\n"));
-        write!(f, "{}", string)
+        ).collect::<Vec<String>>().join("")).collect::<Vec<String>>().join("|\n|").add("|\n").add("+").add(&"-".repeat(self.cols).add("+").add("\n"))
+    }
+}
+
+impl fmt::Display for GameState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(colors_enabled()))
     }
 }
 
-fn next_turn(p:Player) -> Player{
+pub(crate) fn next_turn(p:Player) -> Player{
     match p {
         Player::P1 => Player::P2,
         Player::P2 => Player::P1
     }
 }
 
-pub fn play(mov : Move, gs : &GameState) -> Option<GameState> {
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MoveError {
+    OffBoard,
+    ColumnDisabled,
+    Occupied,
+    /// The cell below is still empty, so a disc cannot come to rest here.
+    Floating,
+    Blocked,
+    /// Pop Out is off, or the bottom disc of the column is not the mover's.
+    CannotPop,
+    /// The game has already been decided.
+    GameOver,
+    /// Every open column is disabled, so there is nothing to play until one is enabled again.
+    NoMoves,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let string = match self {
+            MoveError::OffBoard => "the move is off the board",
+            MoveError::ColumnDisabled => "the column is disabled",
+            MoveError::Occupied => "the cell is already taken",
+            MoveError::Floating => "the cell below is empty",
+            MoveError::Blocked => "the cell is blocked",
+            MoveError::CannotPop => "there is no disc of the mover's to pop",
+            MoveError::GameOver => "the game is already over",
+            MoveError::NoMoves => "every open column is disabled",
+        };
+        write!(f, "{}", string)
+    }
+}
+
+/// Plays `mov`, explaining why it is illegal if it is.
+pub fn try_play(mov : Move, gs : &GameState) -> Result<GameState, MoveError> {
     let Move {row, col} = mov;
-    return match gs.board[row][col] {
+    if row >= gs.rows || col >= gs.cols {
+        return Err(MoveError::OffBoard);
+    }
+    if gs.is_column_disabled(gs.lane_of(mov)) {
+        return Err(MoveError::ColumnDisabled);
+    }
+    if gs.is_blocked(row, col) {
+        return Err(MoveError::Blocked);
+    }
+    match gs.board[row][col] {
         None => {
-            if row != gs.rows - 1 && gs.board[row + 1][col].is_none() {
-                return None;
+            if !is_supported(gs, mov) {
+                return Err(MoveError::Floating);
             }
             let mut copy = gs.clone();
-            copy.board[row][col] = Some(gs.turn);
+            copy.place(mov, gs.turn);
+            raise_height(&mut copy, col);
             copy.turn = next_turn(gs.turn);
-            Some(copy)
+            Ok(copy)
         }
-        _ => { None }
+        _ => Err(MoveError::Occupied)
     }
+}
 
+pub fn play(mov : Move, gs : &GameState) -> Option<GameState> {
+    try_play(mov, gs).ok()
 }
 
-fn legal_in_col(gs: GameState, col : usize ) -> Option<Move> {
-    for row in (0..gs.rows).rev() {
-        if let None = gs.board[row][col] {
-            return Some(Move{row, col});
-        }
-    }
-    None
+/// Drops a disc in `col`, or `None` if the column cannot take one.
+pub fn play_col(col : usize, gs : &GameState) -> Option<GameState> {
+    play(Move::from_col(col, gs)?, gs)
 }
 
-pub fn get_legal (gs : &GameState) -> Vec<Move> {
-    //performance issue?
-    (0..gs.cols).map(|mov| ( legal_in_col(gs.clone(), mov) )).flatten().collect()
+/// A move under any rule: dropping a disc, or under Pop Out, popping one out of the bottom row.
+///
+/// Pop Out is supported by the rules alone. `play`, `get_legal`, the agents and game sessions
+/// only ever drop discs, so a Pop Out game is played by calling `try_play_ply` with one of
+/// `get_legal_plies`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Ply {
+    Drop(Move),
+    Pop(usize),
 }
 
-pub fn result(gs : &GameState) -> Option<GameResult>{
-    for p in vec![Player::P1, Player::P2]{
-        match num_wins(gs, p, false){
-            0 => {}
-            _ => {return Some(GameResult::Win(p))}
-        }
+/// Pulls the mover's disc out of the bottom of `col`, shifting the column down.
+pub fn try_pop(col : usize, gs : &GameState) -> Result<GameState, MoveError> {
+    if col >= gs.cols {
+        return Err(MoveError::OffBoard);
+    }
+    if gs.is_column_disabled(col) {
+        return Err(MoveError::ColumnDisabled);
     }
-    return if is_full(gs) {Some(GameResult::Draw)}else {None};
+    if !gs.pop_out || gs.has_blocked_cells() || gs.gravity != GravityDir::Down || gs.board[gs.rows - 1][col] != Some(gs.turn) {
+        return Err(MoveError::CannotPop);
+    }
+    let mut copy = gs.clone();
+    for row in (1..gs.rows).rev() {
+        copy.board[row][col] = copy.board[row - 1][col];
+    }
+    copy.board[0][col] = None;
+    copy.heights[col] -= 1;
+    copy.turn = next_turn(gs.turn);
+    Ok(copy.refreshed())
 }
 
-fn is_full(gs : &GameState) -> bool{
-    !gs.board.iter().flatten().any(|disc| disc.is_none())
-}
-
-fn win_in_row(gs : &GameState, player : Player, possible_wins : bool) -> i32{
-    let mut wins = 0;
-    for row in 0..gs.rows {
-        let mut in_a_row = 0;
-        for col in 0..gs.cols {
-            match gs.board[row][col] {
-                Some(p) if p==player => {in_a_row +=1}
-                None if possible_wins => {in_a_row +=1}
-                _ => {in_a_row = 0}
-            }
-            if in_a_row == 4{
-                wins+=1;
-                in_a_row = 3;
-            }
-        }
+pub fn try_play_ply(ply : Ply, gs : &GameState) -> Result<GameState, MoveError> {
+    match ply {
+        Ply::Drop(mov) => try_play(mov, gs),
+        Ply::Pop(col) => try_pop(col, gs),
     }
-    return wins;
 }
 
-fn win_in_col(gs : &GameState, player : Player, possible_wins : bool) -> i32{
-    let mut wins = 0;
-    for col in 0..gs.cols {
-        let mut in_a_row = 0;
-        for row in 0..gs.rows {
-            match gs.board[row][col] {
-                Some(p) if p==player => {in_a_row +=1}
-                None if possible_wins => {in_a_row +=1}
-                _ => {in_a_row = 0}
-            }
-            if in_a_row == 4{
-                wins+=1;
-                in_a_row = 3;
-            }
-        }
-    }
-    return wins;
+/// Every legal drop, then under Pop Out every legal pop.
+pub fn get_legal_plies(gs : &GameState) -> Vec<Ply> {
+    let pops = (0..gs.cols).filter(|&col| try_pop(col, gs).is_ok()).map(Ply::Pop);
+    get_legal(gs).into_iter().map(Ply::Drop).chain(pops).collect()
 }
 
-fn win_in_diag_tl_to_br(gs : &GameState, player : Player, possible_wins : bool) -> i32{
-    let mut wins = 0;
-    let starts_side : Vec<(usize, usize)> = (0..gs.rows-3).map(|start_row| (start_row, 0)).collect();
-    let starts_top : Vec<(usize, usize)> = (1..gs.cols-3).map(|start_col| (0, start_col)).collect();
-    for ( start_row, start_col ) in [starts_side, starts_top].concat() {
-        let mut in_a_row = 0;
-        for offset in 0..min::<usize>(gs.rows-start_row, gs.cols-start_col) {
-            match gs.board[start_row+offset][start_col + offset] {
-                Some(p) if p==player => {in_a_row +=1}
-                None if possible_wins => {in_a_row +=1}
-                _ => {in_a_row = 0}
-            }
-            if in_a_row == 4{
-                wins+=1;
-                in_a_row = 3;
-            }
-        }
+/// Plays `mov` in place, the allocation-free counterpart of `play` for searches that undo their
+/// moves with `unmake_move`. The move must be legal.
+pub fn make_move(mov: Move, gs: &mut GameState) {
+    gs.place(mov, gs.turn);
+    raise_height(gs, mov.col);
+    gs.turn = next_turn(gs.turn);
+}
 
+/// Takes back a move made with `make_move`.
+pub fn unmake_move(mov: Move, gs: &mut GameState) {
+    gs.remove(mov);
+    if gs.gravity == GravityDir::Down {
+        gs.heights[mov.col] = gs.rows - 1 - mov.row;
     }
-    return wins;
+    gs.turn = next_turn(gs.turn);
 }
 
-fn win_in_diag_tr_to_bl(gs : &GameState, player : Player, possible_wins : bool) -> i32{
-    let mut wins = 0;
-    let starts_side : Vec<(usize, usize)> = (0..gs.rows-3).map(|start_row| (start_row, gs.cols-1)).collect();
-    let starts_top : Vec<(usize, usize)> = (3..gs.cols-1).map(|start_col| (0, start_col)).collect();
-    for ( start_row, start_col ) in [starts_side, starts_top].concat() {
-        let mut in_a_row = 0;
-        for offset in 0..min::<usize>(gs.rows-start_row, start_col + 1) {
-            match gs.board[start_row+offset][start_col - offset] {
-                Some(p) if p==player => {in_a_row +=1}
-                None if possible_wins => {in_a_row +=1}
-                _ => {in_a_row = 0}
-            }
-            if in_a_row == 4{
-                wins+=1;
-                in_a_row = 3;
-            }
+/// The cell a disc at `mov` rests on, or `None` on the edge that gravity pulls towards.
+fn support(gs : &GameState, mov : Move) -> Option<Move> {
+    match gs.gravity {
+        GravityDir::Down => (mov.row + 1 < gs.rows).then(|| Move::new(mov.row + 1, mov.col)),
+        GravityDir::Up => mov.row.checked_sub(1).map(|row| Move::new(row, mov.col)),
+        GravityDir::Left => mov.col.checked_sub(1).map(|col| Move::new(mov.row, col)),
+        GravityDir::Right => (mov.col + 1 < gs.cols).then(|| Move::new(mov.row, mov.col + 1)),
+    }
+}
+
+/// Whether a disc at `mov` would rest on discs and blocked cells all the way to the edge that
+/// gravity pulls towards. A blocked cell with an empty cell below it holds nothing up.
+fn is_supported(gs : &GameState, mov : Move) -> bool {
+    let mut below = support(gs, mov);
+    while let Some(cell) = below {
+        if gs.board[cell.row][cell.col].is_none() && !gs.is_blocked(cell.row, cell.col) {
+            return false;
         }
+        below = support(gs, cell);
+    }
+    true
+}
 
+/// Counts a disc just dropped in `col`, and the blocked cells it leaves the next disc to pass over.
+/// Heights are only kept for downward gravity.
+fn raise_height(gs : &mut GameState, col : usize) {
+    if gs.gravity != GravityDir::Down {
+        return;
+    }
+    gs.heights[col] += 1;
+    while gs.heights[col] < gs.rows && gs.is_blocked(gs.rows - 1 - gs.heights[col], col) {
+        gs.heights[col] += 1;
     }
-    return wins;
 }
 
-pub fn eval (gs : &GameState) -> f32{
-    num_wins(gs, gs.turn, true) as f32
+/// The discs and blocked cells stacked from the bottom of each column up to its first free cell.
+fn column_heights(board : &[Vec<Disc>], blocked : &HashSet<Move>) -> Vec<usize> {
+    let cols = board.first().map_or(0, |row| row.len());
+    (0..cols).map(|col| column_height(board, blocked, col)).collect()
 }
 
-fn num_wins(gs : &GameState, player : Player, possible_wins : bool ) -> i32 {
-    let tests: Vec<fn(&GameState, Player, bool) -> i32> = vec![win_in_row, win_in_col, win_in_diag_tl_to_br, win_in_diag_tr_to_bl];
-    let mut wins = 0;
-    for f in &tests{
-        wins += f(&gs, player, possible_wins);
+fn column_height(board : &[Vec<Disc>], blocked : &HashSet<Move>, col : usize) -> usize {
+    (0..board.len()).rev()
+        .take_while(|&row| board[row][col].is_some() || (!blocked.is_empty() && blocked.contains(&Move::new(row, col))))
+        .count()
+}
+
+/// The free cell nearest the edge gravity pulls towards in `lane`, passing over discs and blocked
+/// cells. Downward gravity reads it off the heights instead.
+fn landing(gs : &GameState, lane : usize) -> Option<Move> {
+    if gs.is_column_disabled(lane) {
+        return None;
     }
+    let cell = |i : usize| match gs.gravity {
+        GravityDir::Down => Move::new(gs.rows - 1 - i, lane),
+        GravityDir::Up => Move::new(i, lane),
+        GravityDir::Left => Move::new(lane, i),
+        GravityDir::Right => Move::new(lane, gs.cols - 1 - i),
+    };
+    let len = if gs.has_vertical_gravity() { gs.rows } else { gs.cols };
+    (0..len).map(cell).find(|mov| gs.board[mov.row][mov.col].is_none() && !gs.is_blocked(mov.row, mov.col))
+}
 
-    return wins;
+fn legal_in_col(gs: &GameState, col : usize ) -> Option<Move> {
+    if gs.gravity != GravityDir::Down {
+        return landing(gs, col);
+    }
+    debug_assert_eq!(gs.heights[col], column_height(&gs.board, &gs.blocked, col));
+    if gs.is_column_disabled(col) || gs.heights[col] >= gs.rows {
+        return None;
+    }
+    Some(Move{row : gs.rows - 1 - gs.heights[col], col})
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::game_logic::{eval, GameResult, GameState, Player, result};
+pub fn get_legal (gs : &GameState) -> Vec<Move> {
+    legal_moves(gs).collect()
+}
 
-    #[test]
-    fn win_check_horizontal() {
+/// The moves of `get_legal`, for walking them without collecting.
+pub fn legal_moves(gs : &GameState) -> impl Iterator<Item = Move> + '_ {
+    (0..gs.lanes()).filter_map(|lane| legal_in_col(gs, lane))
+}
+
+/// Whether the side to move can drop anywhere, without collecting the moves. An unfinished game
+/// can still have no moves when every open column is disabled.
+pub fn has_legal_move(gs : &GameState) -> bool {
+    (0..gs.lanes()).any(|col| legal_in_col(gs, col).is_some())
+}
+
+/// The playable columns (rows under sideways gravity) as a bitmask with bit `c` set for column
+/// `c`, to walk without allocating, or `None` on a board with more than 64 of them.
+pub fn legal_columns(gs : &GameState) -> Option<u64> {
+    if gs.lanes() > 64 {
+        return None;
+    }
+    Some((0..gs.lanes()).filter(|&col| legal_in_col(gs, col).is_some()).fold(0, |mask, col| mask | 1 << col))
+}
+
+/// Every legal move together with the position it leads to.
+pub fn children(gs : &GameState) -> impl Iterator<Item = (Move, GameState)> + '_ {
+    get_legal(gs).into_iter().map(move |mov| {
+        let mut child = gs.clone();
+        make_move(mov, &mut child);
+        (mov, child)
+    })
+}
+
+/// The number of positions reached by playing exactly `depth` more plies, not counting lines that
+/// end in a result sooner. Pinning these counts catches changes to the move generator.
+pub fn perft(gs : &GameState, depth : usize) -> u64 {
+    fn count(gs : &mut GameState, depth : usize) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        if result(gs).is_some() {
+            return 0;
+        }
+        let mut nodes = 0;
+        for mov in get_legal(gs) {
+            make_move(mov, gs);
+            nodes += count(gs, depth - 1);
+            unmake_move(mov, gs);
+        }
+        nodes
+    }
+    count(&mut gs.clone(), depth)
+}
+
+/// Plays up to `plies` random legal moves from `gs`, stopping early rather than ending the game.
+pub fn get_random_position(gs : &GameState, plies : usize) -> GameState {
+    let mut current = gs.clone();
+    for _ in 0..plies {
+        let moves = get_legal(&current);
+        if moves.is_empty() {
+            break;
+        }
+        let mov = moves[with_rng(|rng| rng.gen_range(0..moves.len()))];
+        let next = play(mov, &current).unwrap();
+        if result(&next).is_some() {
+            break;
+        }
+        current = next;
+    }
+    current
+}
+
+const DIRECTIONS : [(isize, isize); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+const DEFAULT_WIN_LEN : usize = 4;
+
+fn line_starts(rows : usize, cols : usize, win_len : usize) -> impl Iterator<Item = (Move, (isize, isize))> {
+    let fits = move |start : isize, step : isize, len : usize| {
+        let end = start + step * (win_len as isize - 1);
+        end >= 0 && (end as usize) < len
+    };
+    DIRECTIONS.into_iter().flat_map(move |(d_row, d_col)| {
+        (0..rows).flat_map(move |row| (0..cols).map(move |col| Move {row, col}))
+            .filter(move |start| fits(start.row as isize, d_row, rows) && fits(start.col as isize, d_col, cols))
+            .map(move |start| (start, (d_row, d_col)))
+    })
+}
+
+fn line_cells(start : Move, (d_row, d_col) : (isize, isize), win_len : usize) -> impl Iterator<Item = Move> {
+    (0..win_len as isize).map(move |i| Move {
+        row : (start.row as isize + d_row * i) as usize,
+        col : (start.col as isize + d_col * i) as usize,
+    })
+}
+
+/// Every horizontal, vertical and diagonal window of `win_len` cells on the board, the lines a
+/// player can win with.
+pub fn all_lines(gs : &GameState) -> impl Iterator<Item = Vec<Move>> {
+    let win_len = gs.win_len;
+    line_starts(gs.rows, gs.cols, win_len).map(move |(start, direction)| line_cells(start, direction, win_len).collect())
+}
+
+fn completes_line(gs : &GameState, player : Player, mov : Move) -> bool {
+    let run = |d_row : isize, d_col : isize| {
+        let (mut row, mut col, mut len) = (mov.row as isize + d_row, mov.col as isize + d_col, 0);
+        while row >= 0 && col >= 0 && (row as usize) < gs.rows && (col as usize) < gs.cols
+            && gs.board[row as usize][col as usize] == Some(player) {
+            len += 1;
+            row += d_row;
+            col += d_col;
+        }
+        len
+    };
+    DIRECTIONS.iter().any(|&(d_row, d_col)| 1 + run(d_row, d_col) + run(-d_row, -d_col) >= gs.win_len)
+}
+
+fn with_disc(gs : &GameState, player : Player, mov : Move) -> GameState {
+    let mut copy = gs.clone();
+    copy.place(mov, player);
+    copy.set_gravity(gs.gravity);
+    copy.turn = next_turn(player);
+    copy
+}
+
+/// Legal moves that would immediately win for `player`, regardless of whose turn it is.
+pub fn winning_moves(gs : &GameState, player : Player) -> Vec<Move> {
+    get_legal(gs).into_iter().filter(|mov| completes_line(gs, player, *mov)).collect()
+}
+
+/// Every empty cell that would complete a line for `player`, whether or not a disc can be dropped
+/// there yet.
+pub fn threats(gs : &GameState, player : Player) -> Vec<Move> {
+    (0..gs.rows).flat_map(|row| (0..gs.cols).map(move |col| Move::new(row, col)))
+        .filter(|mov| gs.board[mov.row][mov.col].is_none() && !gs.is_blocked(mov.row, mov.col) && completes_line(gs, player, *mov))
+        .collect()
+}
+
+/// The threats of `player` that can be played right now.
+pub fn reachable_threats(gs : &GameState, player : Player) -> Vec<Move> {
+    threats(gs, player).into_iter().filter(|mov| legal_in_col(gs, gs.lane_of(*mov)) == Some(*mov)).collect()
+}
+
+/// A move found without searching: a winning move if there is one, otherwise a move after which
+/// the opponent has no immediate win, or `None` if every move loses on the spot.
+pub fn immediate_best(gs : &GameState) -> Option<Move> {
+    if let Some(mov) = reachable_threats(gs, gs.turn).first() {
+        return Some(*mov);
+    }
+    let opponent = next_turn(gs.turn);
+    children(gs).find(|(_, child)| reachable_threats(child, opponent).is_empty()).map(|(mov, _)| mov)
+}
+
+/// What a move does on the spot, for trying the promising moves first.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum MoveClass {
+    ImmediateWin,
+    /// Leaves the opponent a winning reply, whether by not blocking or by making room for one.
+    AllowsOpponentWin,
+    Neutral,
+}
+
+/// Every legal move with what it does: win outright, hand the opponent a win, or neither.
+pub fn classify_moves(gs : &GameState) -> Vec<(Move, MoveClass)> {
+    let opponent = next_turn(gs.turn);
+    children(gs).map(|(mov, child)| {
+        let class = if completes_line(gs, gs.turn, mov) {
+            MoveClass::ImmediateWin
+        } else if !winning_moves(&child, opponent).is_empty() {
+            MoveClass::AllowsOpponentWin
+        } else {
+            MoveClass::Neutral
+        };
+        (mov, class)
+    }).collect()
+}
+
+/// Whether `mov` is on an odd row counting from 1 at the bottom. When the board fills up, odd
+/// threats tend to favour P1 and even threats P2.
+pub fn is_odd_threat(gs : &GameState, mov : Move) -> bool {
+    (gs.rows - mov.row) % 2 == 1
+}
+
+/// Whether `player` playing `mov` leaves them with at least two immediately winning moves.
+pub fn creates_fork(gs : &GameState, player : Player, mov : Move) -> bool {
+    winning_moves(&with_disc(gs, player, mov), player).len() >= 2
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PlayerThreats {
+    pub immediate_wins: Vec<Move>,
+    pub forks: Vec<Move>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ThreatSummary {
+    pub to_move: Player,
+    pub p1: PlayerThreats,
+    pub p2: PlayerThreats,
+    /// Cells the side to move must occupy to stop the opponent winning next turn.
+    pub required_blocks: Vec<Move>,
+}
+
+impl ThreatSummary {
+    pub fn for_player(&self, player : Player) -> &PlayerThreats {
+        match player {
+            Player::P1 => &self.p1,
+            Player::P2 => &self.p2,
+        }
+    }
+}
+
+/// Immediate wins, forks and required blocks for both players, from a single scan of the legal moves.
+/// Each player's wins are found once; a move that does not win leaves the others in place, so the
+/// fork check only has to look for the wins it adds.
+pub fn threat_summary(gs : &GameState) -> ThreatSummary {
+    let mut p1 = PlayerThreats { immediate_wins : winning_moves(gs, Player::P1), forks : Vec::new() };
+    let mut p2 = PlayerThreats { immediate_wins : winning_moves(gs, Player::P2), forks : Vec::new() };
+    for mov in legal_moves(gs) {
+        for (player, threats) in [(Player::P1, &mut p1), (Player::P2, &mut p2)] {
+            let wins = &threats.immediate_wins;
+            if wins.contains(&mov) {
+                continue;
+            }
+            let forks = wins.len() >= 2 || {
+                let child = with_disc(gs, player, mov);
+                let added = legal_moves(&child).filter(|next| !wins.contains(next) && completes_line(&child, player, *next)).count();
+                wins.len() + added >= 2
+            };
+            if forks {
+                threats.forks.push(mov);
+            }
+        }
+    }
+    let required_blocks = match gs.turn {
+        Player::P1 => p2.immediate_wins.clone(),
+        Player::P2 => p1.immediate_wins.clone(),
+    };
+    ThreatSummary {
+        to_move : gs.turn,
+        p1,
+        p2,
+        required_blocks,
+    }
+}
+
+const FNV_OFFSET : u64 = 0xcbf29ce484222325;
+const FNV_PRIME : u64 = 0x100000001b3;
+
+/// Order-sensitive FNV-1a hash of a move sequence, stable across runs and platforms.
+pub fn game_fingerprint(moves : &[Move]) -> u64 {
+    let mut hash = FNV_OFFSET;
+    for Move {row, col} in moves {
+        for byte in (*row as u64).to_le_bytes().into_iter().chain((*col as u64).to_le_bytes()) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    hash
+}
+
+pub fn position_hash(gs : &GameState) -> u64 {
+    let mut hash = FNV_OFFSET;
+    for disc in gs.board.iter().flatten().chain([&Some(gs.turn)]) {
+        hash ^= match disc {
+            None => 0,
+            Some(Player::P1) => 1,
+            Some(Player::P2) => 2,
+        };
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+pub fn disc_count(gs : &GameState) -> usize {
+    debug_assert_eq!(gs.placed, gs.board.iter().flatten().filter(|disc| disc.is_some()).count());
+    gs.placed
+}
+
+/// The position packed two bits per cell, with a fourth value for blocked cells, followed by the
+/// side to move, a word for the other rules and then, if any are, a bit for each disabled column.
+pub fn packed_board(gs : &GameState) -> Vec<u64> {
+    pack(gs, false)
+}
+
+/// Packs the position, or its mirror image when `mirror` is set.
+fn pack(gs : &GameState, mirror : bool) -> Vec<u64> {
+    packed_words(gs, mirror).collect()
+}
+
+/// The words of `pack` one at a time, so that two packings can be compared without building
+/// either.
+fn packed_words(gs : &GameState, mirror : bool) -> impl Iterator<Item = u64> + '_ {
+    let code = |disc : Disc| match disc {
+        None => 0,
+        Some(Player::P1) => 1,
+        Some(Player::P2) => 2,
+    };
+    let cells = (0..gs.rows).flat_map(move |row| (0..gs.cols).map(move |col| Move::new(row, if mirror { gs.cols - 1 - col } else { col })));
+    let mut codes = cells.map(move |mov| if gs.is_blocked(mov.row, mov.col) { 3 } else { code(gs.board[mov.row][mov.col]) })
+        .chain([code(Some(gs.turn))]);
+    let cell_words = (0..(gs.rows * gs.cols * 2 + 2).div_ceil(64))
+        .map(move |_| codes.by_ref().take(32).enumerate().fold(0u64, |word, (i, bits)| word | bits << (i * 2)));
+    let disabled_words = if gs.disabled_columns.is_empty() { 0 } else { gs.lanes().div_ceil(64) };
+    let disabled = (0..disabled_words)
+        .map(move |word| disabled_lanes(gs, mirror).filter(|lane| lane / 64 == word).fold(0u64, |bits, lane| bits | 1 << (lane % 64)));
+    cell_words.chain([rules_word(gs)]).chain(disabled)
+}
+
+/// The win length, gravity and Pop Out in one word.
+fn rules_word(gs : &GameState) -> u64 {
+    gs.win_len as u64 | (gs.gravity as u64) << 56 | (gs.pop_out as u64) << 60
+}
+
+/// The disabled lanes that exist on the board, mirrored when `mirror` is set.
+fn disabled_lanes(gs : &GameState, mirror : bool) -> impl Iterator<Item = usize> + '_ {
+    gs.disabled_columns.iter()
+        .filter(|&&lane| lane < gs.lanes())
+        .map(move |&lane| if mirror { gs.lanes() - 1 - lane } else { lane })
+}
+
+/// Under Pop Out a pop can complete lines for both players at once, and then the player who
+/// popped wins. A full board is only a draw once the side to move cannot pop either.
+pub fn result(gs : &GameState) -> Option<GameResult>{
+    let players = if gs.pop_out { [next_turn(gs.turn), gs.turn] } else { [Player::P1, Player::P2] };
+    for p in players{
+        match num_wins(gs, p, false){
+            0 => {}
+            _ => {return Some(GameResult::Win(p))}
+        }
+    }
+    if is_full(gs) && !can_pop(gs) {Some(GameResult::Draw)}else {None}
+}
+
+fn can_pop(gs : &GameState) -> bool {
+    gs.pop_out && (0..gs.cols).any(|col| try_pop(col, gs).is_ok())
+}
+
+pub fn is_symmetrical(gs : &GameState) -> bool {
+    gs.has_vertical_gravity()
+        && gs.board.iter().all(|row| row.iter().eq(row.iter().rev()))
+        && gs.disabled_columns.iter().all(|col| gs.is_column_disabled(gs.cols - 1 - col))
+        && gs.blocked.iter().all(|mov| gs.is_blocked(mov.row, gs.cols - 1 - mov.col))
+}
+
+/// The cells of a completed line and whose they are. When several lines are complete any one of
+/// them is returned.
+pub fn winning_line(gs : &GameState) -> Option<(Player, Vec<Move>)> {
+    line_starts(gs.rows, gs.cols, gs.win_len).find_map(|(start, direction)| {
+        let player = gs.board[start.row][start.col]?;
+        let line : Vec<Move> = line_cells(start, direction, gs.win_len).collect();
+        line.iter().all(|mov| gs.board[mov.row][mov.col] == Some(player)).then_some((player, line))
+    })
+}
+
+/// Whether neither player has a line left that they could still complete, so the game is a dead draw.
+pub fn no_wins_possible(gs : &GameState) -> bool {
+    num_wins(gs, Player::P1, true) == 0 && num_wins(gs, Player::P2, true) == 0
+}
+
+/// Whether every cell holds a disc. Discs pass over blocked cells, so the board is full once every
+/// other cell does. Disabled columns don't count: they can be enabled again, so a position where
+/// every open column is disabled has no moves but isn't over.
+fn is_full(gs : &GameState) -> bool{
+    gs.placed + gs.blocked.len() == gs.rows * gs.cols
+}
+
+pub fn eval (gs : &GameState) -> f32{
+    num_wins(gs, gs.turn, true) as f32
+}
+
+/// The number of lines P1 and P2 could each still complete, the building blocks of `utility`.
+pub fn potential_wins(gs : &GameState) -> (i32, i32) {
+    (num_wins(gs, Player::P1, true), num_wins(gs, Player::P2, true))
+}
+
+/// Like `potential_wins`, but each line P1 or P2 could still complete counts `weights[n]` when
+/// they already have `n` discs in it, so that a line close to completion outweighs an empty one.
+/// Counts past the end of `weights` use its last entry.
+pub fn weighted_potential_wins(gs : &GameState, weights : &[f32]) -> (f32, f32) {
+    let weigh = |player| line_starts(gs.rows, gs.cols, gs.win_len)
+        .filter_map(|(start, direction)| {
+            let mut discs = 0;
+            for Move {row, col} in line_cells(start, direction, gs.win_len) {
+                match gs.board[row][col] {
+                    Some(p) if p == player => discs += 1,
+                    Some(_) => return None,
+                    None if gs.is_blocked(row, col) => return None,
+                    None => {}
+                }
+            }
+            weights.get(discs).or(weights.last()).copied()
+        })
+        .sum();
+    (weigh(Player::P1), weigh(Player::P2))
+}
+
+/// How central `col` is, from `cols - 1` in the middle down to 0 on the edges.
+pub(crate) fn centrality(col : usize, cols : usize) -> i32 {
+    (cols - 1) as i32 - (2 * col as i32 - (cols - 1) as i32).abs()
+}
+
+/// The columns of a `cols` wide board from the center out, the left one first on ties, e.g.
+/// `3 2 4 1 5 0 6` for 7 columns.
+pub fn center_out(cols : usize) -> Vec<usize> {
+    let mut order : Vec<usize> = (0..cols).collect();
+    order.sort_by_key(|&col| (-centrality(col, cols), col));
+    order
+}
+
+/// Scales a sum of `centrality` so that even a full board of central discs adds up to less than
+/// one possible win. The sum is kept in integers so that mirrored positions score exactly the same.
+pub(crate) fn center_bonus_from(centrality_sum : i32, rows : usize, cols : usize) -> f32 {
+    centrality_sum as f32 / ((cols - 1).max(1) * (rows * cols + 1)) as f32
+}
+
+/// P1's positional edge from discs near the center, minus P2's.
+pub fn center_bonus(gs : &GameState) -> f32 {
+    let sum = gs.board.iter().flat_map(|row| row.iter().enumerate()).map(|(col, disc)| match disc {
+        Some(Player::P1) => centrality(col, gs.cols),
+        Some(Player::P2) => -centrality(col, gs.cols),
+        None => 0,
+    }).sum();
+    center_bonus_from(sum, gs.rows, gs.cols)
+}
+
+/// Static evaluation from P1's perspective: infinite for decided games, 0 for draws,
+/// otherwise the difference in possible wins with `center_bonus` to break ties.
+pub fn utility(gs : &GameState) -> f32 {
+    match result(gs) {
+        Some(GameResult::Win(Player::P1)) => f32::INFINITY,
+        Some(GameResult::Win(Player::P2)) => f32::NEG_INFINITY,
+        Some(GameResult::Draw) => 0.0,
+        None => {
+            let (p1, p2) = potential_wins(gs);
+            (p1 - p2) as f32 + center_bonus(gs)
+        }
+    }
+}
+
+/// Everything that follows from dropping a disc in a column, without committing to it.
+#[derive(Clone, Debug)]
+pub struct MovePreview {
+    pub mov: Move,
+    pub state: GameState,
+    pub result: Option<GameResult>,
+    pub eval: f32,
+    pub threats: ThreatSummary,
+}
+
+/// Previews dropping a disc in `col`, or `None` if the column is full or off the board.
+pub fn preview_move(gs : &GameState, col : usize) -> Option<MovePreview> {
+    let mov = Move::drop(gs, col)?;
+    let state = play(mov, gs)?;
+    Some(MovePreview {
+        mov,
+        result : result(&state),
+        eval : utility(&state),
+        threats : threat_summary(&state),
+        state,
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EvalParams {
+    /// Subtracted from a move that hands the opponent a threat of their own parity on the square
+    /// directly above it.
+    pub threat_above_penalty: f32,
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        Self {
+            threat_above_penalty: 10.0,
+        }
+    }
+}
+
+/// P1 profits from threats on odd rows and P2 from even rows, counting from 1 at the bottom.
+fn favoured_parity(gs : &GameState, player : Player, row : usize) -> bool {
+    let from_bottom = gs.rows - row;
+    match player {
+        Player::P1 => from_bottom % 2 == 1,
+        Player::P2 => from_bottom.is_multiple_of(2),
+    }
+}
+
+/// Evaluates the legal move `mov` from the perspective of the side to move.
+pub fn eval_move(gs : &GameState, mov : Move, params : &EvalParams) -> f32 {
+    let child = play(mov, gs).expect("eval_move called with an illegal move");
+    let value = match gs.turn {
+        Player::P1 => utility(&child),
+        Player::P2 => -utility(&child),
+    };
+    let opponent = next_turn(gs.turn);
+    match mov.row.checked_sub(1).map(|row| Move::new(row, mov.col)) {
+        Some(above) if completes_line(&child, opponent, above) && favoured_parity(gs, opponent, above.row) =>
+            value - params.threat_above_penalty,
+        _ => value
+    }
+}
+
+fn sort_ranked(mut ranked : Vec<(Move, f32)>) -> Vec<(Move, f32)> {
+    ranked.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    ranked
+}
+
+/// Every legal move with its `eval_move` score, best first. Equal scores keep column order.
+pub fn ranked_moves(gs : &GameState, params : &EvalParams) -> Vec<(Move, f32)> {
+    sort_ranked(get_legal(gs).into_iter().map(|mov| (mov, eval_move(gs, mov, params))).collect())
+}
+
+/// `ranked_moves` with the moves evaluated concurrently, for analysis on wide boards.
+#[cfg(feature = "parallel")]
+pub fn ranked_moves_parallel(gs : &GameState, params : &EvalParams) -> Vec<(Move, f32)> {
+    sort_ranked(get_legal(gs).into_par_iter().map(|mov| (mov, eval_move(gs, mov, params))).collect())
+}
+
+fn num_wins(gs : &GameState, player : Player, possible_wins : bool ) -> i32 {
+    line_starts(gs.rows, gs.cols, gs.win_len)
+        .filter(|&(start, direction)| line_cells(start, direction, gs.win_len).all(|Move {row, col}| match gs.board[row][col] {
+            Some(p) => p == player,
+            None => possible_wins && !gs.is_blocked(row, col),
+        }))
+        .count() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game_logic::{all_lines, center_bonus, center_out, children, perft, threats, reachable_threats, is_odd_threat, immediate_best, BoardError, winning_line, ParseError, creates_fork, play, play_col, try_play, MoveError, eval, eval_move, get_random_position, potential_wins, preview_move, utility, EvalParams, from_uci_dataset_row, game_fingerprint, get_legal, GameResult, GameState, Move, Player, result, threat_summary, winning_moves};
+    use crate::game_logic::{weighted_potential_wins, classify_moves, column_heights, legal_columns, make_move, MoveClass, unmake_move, no_wins_possible, get_legal_plies, has_legal_move, packed_board, try_play_ply, GravityDir, Ply};
+
+    #[test]
+    fn win_check_horizontal() {
         let gs = GameState::new_from_board(
             vec2d![
                 [0,0,0,1,1,1,1],
@@ -268,6 +1580,7 @@ mod tests {
             ]
         );
         assert_eq!(result(&gs), Some(GameResult::Win(Player::P1)));
+        assert_eq!(winning_line(&gs), Some((Player::P1, vec![Move::new(0, 3), Move::new(0, 4), Move::new(0, 5), Move::new(0, 6)])));
         let gs = GameState::new_from_board(
             vec2d![
                 [0,0,0,0,0,0,0],
@@ -304,6 +1617,7 @@ mod tests {
             ]
         );
         assert_eq!(result(&gs), Some(GameResult::Win(Player::P1)));
+        assert_eq!(winning_line(&gs), Some((Player::P1, vec![Move::new(0, 0), Move::new(1, 0), Move::new(2, 0), Move::new(3, 0)])));
         let gs = GameState::new_from_board(
             vec2d![
                 [0,0,0,0,0,0,0],
@@ -340,6 +1654,7 @@ mod tests {
             ]
         );
         assert_eq!(result(&gs), Some(GameResult::Win(Player::P1)));
+        assert_eq!(winning_line(&gs), Some((Player::P1, vec![Move::new(0, 0), Move::new(1, 1), Move::new(2, 2), Move::new(3, 3)])));
         let gs = GameState::new_from_board(
             vec2d![
                 [0,0,0,0,0,0,0],
@@ -387,6 +1702,7 @@ mod tests {
             ]
         );
         assert_eq!(result(&gs), Some(GameResult::Win(Player::P1)));
+        assert_eq!(winning_line(&gs), Some((Player::P1, vec![Move::new(0, 6), Move::new(1, 5), Move::new(2, 4), Move::new(3, 3)])));
         let gs = GameState::new_from_board(
             vec2d![
                 [0,0,0,0,0,0,0],
@@ -487,4 +1803,925 @@ mod tests {
         );
         assert_eq!(eval(&gs), 0.0);
     }
+
+    #[test]
+    fn fingerprint() {
+        let moves = vec![Move{row: 5, col: 3}, Move{row: 5, col: 4}, Move{row: 4, col: 3}];
+        let same = moves.clone();
+        let reordered = vec![Move{row: 5, col: 4}, Move{row: 5, col: 3}, Move{row: 4, col: 3}];
+        assert_eq!(game_fingerprint(&moves), game_fingerprint(&same));
+        assert_ne!(game_fingerprint(&moves), game_fingerprint(&reordered));
+        assert_ne!(game_fingerprint(&moves), game_fingerprint(&moves[..2]));
+    }
+
+    #[test]
+    fn uci_dataset_import() {
+        let row = "x,o,b,b,b,b,b,b,b,b,b,b,o,b,b,b,b,b,x,b,b,b,b,b,b,b,b,b,b,b,b,b,b,b,b,b,b,b,b,b,b,b,win";
+        let fields : Vec<&str> = row.split(',').collect();
+        let gs = from_uci_dataset_row(&fields).unwrap();
+        assert!(gs == GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [2,0,0,0,0,0,0],
+                [1,0,2,1,0,0,0]
+            ]
+        ));
+        assert_eq!(gs.turn, Player::P1);
+        assert!(from_uci_dataset_row(&fields[..41]).is_err());
+        let mut bad = fields.clone();
+        bad[5] = "y";
+        assert!(from_uci_dataset_row(&bad).is_err());
+    }
+
+    #[test]
+    fn threat_summary_matches_helpers() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,1,2],
+                [0,0,2,2,0,1,2],
+                [0,0,1,1,0,1,2]
+            ]
+        );
+        let summary = threat_summary(&gs);
+        assert_eq!(summary.to_move, Player::P1);
+        assert_eq!(summary.p1.immediate_wins, vec![Move{row: 5, col: 4}, Move{row: 2, col: 5}]);
+        assert_eq!(summary.p2.immediate_wins, vec![Move{row: 2, col: 6}]);
+        assert_eq!(summary.required_blocks, summary.p2.immediate_wins);
+        assert!(summary.p1.forks.contains(&Move{row: 5, col: 1}));
+        crate::rng::seed(205);
+        let positions = (0..200).map(|i| get_random_position(&GameState::new(), i % 30));
+        for gs in std::iter::once(gs).chain(positions) {
+            let summary = threat_summary(&gs);
+            for player in [Player::P1, Player::P2] {
+                let threats = summary.for_player(player);
+                assert_eq!(threats.immediate_wins, winning_moves(&gs, player));
+                let forks : Vec<Move> = get_legal(&gs).into_iter()
+                    .filter(|mov| !threats.immediate_wins.contains(mov) && creates_fork(&gs, player, *mov))
+                    .collect();
+                assert_eq!(threats.forks, forks, "{}", gs);
+            }
+        }
+        crate::rng::reset_rng();
+    }
+
+    #[test]
+    fn move_drop() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,2,0,0,0],
+                [0,0,0,1,0,0,0],
+                [0,0,0,2,0,0,0],
+                [0,0,0,1,0,0,0],
+                [0,0,0,2,0,0,0],
+                [0,1,0,1,0,0,0]
+            ]
+        );
+        assert_eq!(Move::drop(&gs, 0), Some(Move::new(5, 0)));
+        assert_eq!(Move::drop(&gs, 1), Some(Move::new(4, 1)));
+        assert_eq!(Move::drop(&gs, 3), None);
+        assert_eq!(Move::drop(&gs, 7), None);
+    }
+
+    #[test]
+    fn eval_penalizes_threat_above() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,2,2,2,0,0,1],
+                [0,1,2,1,0,0,1]
+            ]
+        );
+        let params = EvalParams::default();
+        let unpenalized = EvalParams { threat_above_penalty: 0.0 };
+        let (unsafe_move, safe_move) = (Move::new(5, 4), Move::new(5, 5));
+        assert!(eval_move(&gs, safe_move, &params) > eval_move(&gs, unsafe_move, &params));
+        assert_eq!(eval_move(&gs, unsafe_move, &params), eval_move(&gs, unsafe_move, &unpenalized) - params.threat_above_penalty);
+        assert_eq!(eval_move(&gs, safe_move, &params), eval_move(&gs, safe_move, &unpenalized));
+    }
+
+    #[test]
+    fn preview_reports_win_and_blunder() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,2,0,0,0,0],
+                [0,0,2,0,0,0,0],
+                [0,0,2,1,1,1,0]
+            ]
+        );
+        let win = preview_move(&gs, 6).unwrap();
+        assert_eq!(win.mov, Move::new(5, 6));
+        assert_eq!(win.result, Some(GameResult::Win(Player::P1)));
+        assert_eq!(win.eval, f32::INFINITY);
+        let blunder = preview_move(&gs, 0).unwrap();
+        assert_eq!(blunder.result, None);
+        assert_eq!(blunder.threats.for_player(Player::P2).immediate_wins, vec![Move::new(2, 2)]);
+        let block = preview_move(&gs, 2).unwrap();
+        assert!(block.threats.for_player(Player::P2).immediate_wins.is_empty());
+        assert!(preview_move(&gs, 7).is_none());
+    }
+
+    #[test]
+    fn potential_wins_make_up_utility() {
+        crate::rng::seed(216);
+        for plies in 0..30 {
+            let gs = get_random_position(&GameState::new(), plies);
+            let (p1, p2) = potential_wins(&gs);
+            assert_eq!((p1 - p2) as f32 + center_bonus(&gs), utility(&gs));
+            assert!(center_bonus(&gs).abs() < 1.0);
+            assert_eq!(eval(&gs), match gs.turn { Player::P1 => p1, Player::P2 => p2 } as f32);
+        }
+        crate::rng::reset_rng();
+    }
+
+    #[test]
+    fn all_lines_covers_standard_board() {
+        let gs = GameState::new();
+        let lines: Vec<Vec<Move>> = all_lines(&gs).collect();
+        assert_eq!(lines.len(), 69);
+        assert!(lines.contains(&vec![Move::new(2, 3), Move::new(3, 2), Move::new(4, 1), Move::new(5, 0)]));
+        assert!(lines.iter().all(|line| line.len() == 4));
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn parallel_ranking_matches_sequential() {
+        use crate::game_logic::{ranked_moves, ranked_moves_parallel};
+        crate::rng::seed(222);
+        let params = EvalParams::default();
+        let wide = GameState::new_from_board(vec![vec![0; 15]; 6]);
+        for plies in 0..30 {
+            let gs = get_random_position(&wide, plies);
+            if result(&gs).is_none() {
+                let ranked = ranked_moves(&gs, &params);
+                assert_eq!(ranked.len(), get_legal(&gs).len());
+                assert!(ranked.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+                assert_eq!(ranked_moves_parallel(&gs, &params), ranked);
+            }
+        }
+    }
+
+    #[test]
+    fn plain_symbols_have_no_escape_codes() {
+        for player in [Player::P1, Player::P2] {
+            assert!(player.symbol(true).contains('\u{001b}'));
+            assert!(!player.symbol(false).contains('\u{001b}'));
+            assert!(player.to_string().contains(&player.symbol(false)));
+        }
+        assert_eq!(Player::P2.symbol(false), "P2");
+        let gs = play_col(3, &GameState::new()).unwrap();
+        assert!(!gs.render(false).contains('\u{001b}'));
+        assert_eq!(gs.render(false).lines().nth(7), Some("|...O...|"));
+    }
+
+    #[test]
+    fn plain_rendering_is_ascii() {
+        crate::rng::seed(278);
+        let gs = get_random_position(&GameState::new(), 20);
+        assert!(gs.render(false).lines().skip(1).flat_map(str::chars).all(|c| "+-|.O".contains(c)), "{}", gs.render(false));
+        assert!(gs.render(true).contains('\u{001b}'));
+        assert_eq!(GameResult::Draw.render(false), "Draw");
+        assert_eq!(GameResult::Win(Player::P1).render(false), "P1");
+    }
+
+    #[test]
+    fn rendering_numbers_the_columns() {
+        assert_eq!(GameState::new().render(false).lines().next(), Some(" 0123456"));
+        let wide = GameState::new_with_size(6, 10).render(true);
+        assert_eq!(wide.lines().next(), Some(" 0123456789"));
+        assert_eq!(wide.lines().nth(1), Some("+----------+"));
+        assert_eq!(GameState::new_with_size(4, 12).render(false).lines().next(), Some(" 012345678901"));
+    }
+
+    #[test]
+    fn finds_a_single_threat() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,2,2,0,0,0,0],
+                [1,1,1,0,0,0,0]
+            ]
+        );
+        assert_eq!(threats(&gs, Player::P1), vec![Move::new(5, 3)]);
+        assert_eq!(reachable_threats(&gs, Player::P1), vec![Move::new(5, 3)]);
+        assert!(is_odd_threat(&gs, Move::new(5, 3)));
+        assert!(threats(&gs, Player::P2).is_empty());
+    }
+
+    #[test]
+    fn finds_stacked_threats() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [1,1,1,0,0,0,0],
+                [1,1,1,0,0,0,0],
+                [2,2,2,0,0,0,0]
+            ]
+        );
+        assert_eq!(threats(&gs, Player::P1), vec![Move::new(3, 3), Move::new(4, 3)]);
+        assert!(reachable_threats(&gs, Player::P1).is_empty());
+        assert_eq!(reachable_threats(&gs, Player::P2), vec![Move::new(5, 3)]);
+        assert!(is_odd_threat(&gs, Move::new(3, 3)));
+        assert!(!is_odd_threat(&gs, Move::new(4, 3)));
+    }
+
+    #[test]
+    fn immediate_best_wins_blocks_or_gives_up() {
+        let win = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,2,2,0,0,0,0],
+                [1,1,1,0,0,0,2]
+            ]
+        );
+        assert_eq!(immediate_best(&win), Some(Move::new(5, 3)));
+
+        let play_cols = |cols : &[usize]| cols.iter().fold(GameState::new(), |gs, col| play_col(*col, &gs).unwrap());
+        let block = play_cols(&[1, 0, 2, 0, 3]);
+        assert_eq!(immediate_best(&block), Some(Move::new(5, 4)));
+        let lost = play_cols(&[2, 0, 3, 0, 4]);
+        assert_eq!(immediate_best(&lost), None);
+    }
+
+    #[test]
+    fn get_legal_matches_a_column_scan() {
+        crate::rng::seed(282);
+        for i in 0..1000 {
+            let gs = get_random_position(&GameState::new(), i % 43);
+            let scanned : Vec<Move> = (0..gs.cols())
+                .filter_map(|col| (0..gs.rows()).rev().find(|row| gs.disc_at(*row, col).is_none()).map(|row| Move::new(row, col)))
+                .collect();
+            assert_eq!(get_legal(&gs), scanned, "{}", gs);
+        }
+    }
+
+    #[test]
+    fn zobrist_hash_follows_the_board() {
+        use rand::Rng;
+        crate::rng::seed(307);
+        for _ in 0..1000 {
+            let mut gs = GameState::new();
+            let mut played = Vec::new();
+            while result(&gs).is_none() {
+                let moves = get_legal(&gs);
+                let mov = moves[crate::rng::with_rng(|rng| rng.gen_range(0..moves.len()))];
+                if played.len() % 2 == 0 {
+                    gs = play(mov, &gs).unwrap();
+                } else {
+                    make_move(mov, &mut gs);
+                }
+                played.push(mov);
+                let fresh = gs.clone().refreshed();
+                assert_eq!((gs.disc_keys, gs.mirror_disc_keys), (fresh.disc_keys, fresh.mirror_disc_keys));
+                assert_eq!(gs.mirrored().zobrist(), gs.mirrored().refreshed().zobrist());
+            }
+            let end = gs.clone();
+            for mov in played.iter().rev() {
+                unmake_move(*mov, &mut gs);
+            }
+            assert_eq!(gs.zobrist(), GameState::new().zobrist());
+            assert_eq!(GameState::from_compact(&end.to_string_compact()).unwrap().zobrist(), end.zobrist());
+        }
+        let gs = play_col(3, &GameState::new()).unwrap();
+        assert_ne!(gs.zobrist(), GameState::new().zobrist());
+        assert_ne!(play_col(2, &gs).unwrap().zobrist(), play_col(4, &gs).unwrap().zobrist());
+        assert_eq!(play_col(2, &gs).unwrap().canonical().zobrist(), play_col(4, &gs).unwrap().canonical().zobrist());
+    }
+
+    #[test]
+    fn canonical_key_matches_canonical_form() {
+        crate::rng::seed(308);
+        for i in 0..500 {
+            let gs = get_random_position(&GameState::new(), i % 42);
+            let canonical = gs.canonical();
+            assert_eq!(gs.canonical_key(), (canonical.zobrist(), crate::game_logic::packed_board(&canonical)), "{}", gs);
+        }
+    }
+
+    #[test]
+    fn rules_change_the_key() {
+        let gs = play_col(0, &GameState::new()).unwrap();
+        let mut disabled = gs.clone();
+        disabled.disable_column(2);
+        assert_ne!(disabled.zobrist(), gs.zobrist());
+        assert_ne!(packed_board(&disabled), packed_board(&gs));
+        assert_eq!(disabled.mirrored().canonical_key(), disabled.canonical_key());
+        let canonical = disabled.canonical();
+        assert_eq!(disabled.canonical_key(), (canonical.zobrist(), packed_board(&canonical)));
+        disabled.enable_column(2);
+        assert_eq!((disabled.zobrist(), packed_board(&disabled)), (gs.zobrist(), packed_board(&gs)));
+
+        let rules = [
+            GameState::new_with_win_len(6, 7, 5),
+            GameState::new_from_board(vec![vec![0; 7], vec![0; 7], vec![0; 7], vec![0; 7], vec![0; 7], vec![-1, 0, 0, 0, 0, 0, 0]]),
+            GameState { pop_out : true, ..GameState::new() },
+        ];
+        for other in rules {
+            assert_ne!(other.zobrist(), GameState::new().zobrist());
+            assert_ne!(packed_board(&other), packed_board(&GameState::new()));
+            let canonical = other.canonical();
+            assert_eq!(other.canonical_key(), (canonical.zobrist(), packed_board(&canonical)));
+        }
+    }
+
+    #[test]
+    fn legal_columns_match_get_legal() {
+        let mask_of = |gs : &GameState| Some(get_legal(gs).iter().fold(0u64, |mask, mov| mask | 1 << mov.col()));
+        crate::rng::seed(310);
+        for i in 0..1000 {
+            let gs = get_random_position(&GameState::new(), i % 43);
+            assert_eq!(legal_columns(&gs), mask_of(&gs), "{}", gs);
+        }
+        let mut gs = GameState::new_from_board(
+            vec2d![
+                [1,0,0,2,0,0,0],
+                [2,0,0,1,0,0,0],
+                [1,0,0,2,0,0,0],
+                [2,0,0,1,0,0,0],
+                [1,0,0,2,0,0,0],
+                [2,0,0,1,0,0,1]
+            ]
+        );
+        assert_eq!(legal_columns(&gs), Some(0b1110110));
+        gs.disable_column(6);
+        assert_eq!(legal_columns(&gs), Some(0b0110110));
+        assert_eq!(legal_columns(&gs), mask_of(&gs));
+        let wide = GameState::new_with_size(4, 64);
+        assert_eq!(legal_columns(&wide), Some(u64::MAX));
+        assert_eq!(legal_columns(&GameState::new_with_size(4, 65)), None);
+    }
+
+    #[test]
+    fn classifies_winning_and_losing_moves() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,2,0,0,0],
+                [0,0,0,2,2,0,0],
+                [0,1,1,1,2,1,0]
+            ]
+        );
+        let classes = classify_moves(&gs);
+        assert_eq!(classes.len(), 7);
+        for (mov, class) in classes {
+            let expected = match mov.col() {
+                0 => MoveClass::ImmediateWin,
+                _ => MoveClass::Neutral,
+            };
+            assert_eq!(class, expected, "{:?}", mov);
+        }
+        // P2 has to block column 3, and anything else lets P1 win there.
+        let mut gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [1,1,1,0,2,2,0]
+            ]
+        );
+        gs.turn = Player::P2;
+        for (mov, class) in classify_moves(&gs) {
+            let expected = if mov.col() == 3 { MoveClass::Neutral } else { MoveClass::AllowsOpponentWin };
+            assert_eq!(class, expected, "{:?}", mov);
+        }
+    }
+
+    #[test]
+    fn weighted_wins_favour_fuller_lines() {
+        let weights = [0.0, 1.0, 10.0, 100.0];
+        let empty = GameState::new();
+        assert_eq!(weighted_potential_wins(&empty, &weights), (0.0, 0.0));
+        assert_eq!(weighted_potential_wins(&empty, &[1.0]), (69.0, 69.0));
+        let threat = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [2,0,0,0,0,0,0],
+                [2,1,1,1,0,2,0]
+            ]
+        );
+        let spread = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [2,0,0,0,0,0,0],
+                [2,1,0,1,0,2,1]
+            ]
+        );
+        assert!(weighted_potential_wins(&threat, &weights).0 > weighted_potential_wins(&spread, &weights).0);
+        // Unweighted, the spread-out discs keep more lines open.
+        assert!(potential_wins(&threat).0 <= potential_wins(&spread).0);
+        let (p1, p2) = weighted_potential_wins(&threat, &[1.0]);
+        assert_eq!((p1 as i32, p2 as i32), potential_wins(&threat));
+    }
+
+    #[test]
+    fn results_score_from_either_side() {
+        let p1_wins = GameResult::Win(Player::P1);
+        let p2_wins = GameResult::Win(Player::P2);
+        assert_eq!(p1_wins.winner(), Some(Player::P1));
+        assert_eq!(p2_wins.winner(), Some(Player::P2));
+        assert_eq!(GameResult::Draw.winner(), None);
+        assert_eq!((p1_wins.score_for(Player::P1), p1_wins.score_for(Player::P2)), (1.0, 0.0));
+        assert_eq!((p2_wins.score_for(Player::P1), p2_wins.score_for(Player::P2)), (0.0, 1.0));
+        assert_eq!((GameResult::Draw.score_for(Player::P1), GameResult::Draw.score_for(Player::P2)), (0.5, 0.5));
+    }
+
+    #[test]
+    fn fullness_follows_the_disc_count() {
+        let full = vec2d![
+            [1,2,1,2],
+            [2,1,2,1],
+            [1,2,1,2]
+        ];
+        assert_eq!(result(&GameState::new_from_board(full.clone())), Some(GameResult::Draw));
+        let mut one_short = full;
+        one_short[0][3] = 0;
+        let gs = GameState::new_from_board(one_short);
+        assert_eq!(result(&gs), None);
+        assert_eq!(gs.placed_count(), 11);
+        let mut stuck = gs.clone();
+        stuck.disable_column(3);
+        assert_eq!((result(&stuck), has_legal_move(&stuck)), (None, false));
+        stuck.enable_column(3);
+        assert!(has_legal_move(&stuck));
+        assert_eq!(result(&play_col(3, &gs).unwrap()), Some(GameResult::Draw));
+        let mut gs = gs;
+        unmake_move(Move::new(1, 3), &mut gs);
+        assert_eq!((gs.placed_count(), result(&gs)), (10, None));
+        let blocked = GameState::new_from_board(vec2d![[-1,2],[1,1],[2,-1]]);
+        assert_eq!(result(&blocked), Some(GameResult::Draw));
+    }
+
+    #[test]
+    fn either_player_can_start() {
+        let gs = GameState::new_with_turn(6, 7, Player::P2);
+        assert_eq!(gs.turn(), Player::P2);
+        assert_eq!(play_col(3, &gs).unwrap().disc_at(5, 3), Some(Player::P2));
+        assert_eq!(GameState::new_with_turn(6, 7, Player::P1), GameState::new());
+    }
+
+    #[test]
+    fn accessors_describe_the_board() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0],
+                [0,0,0,0],
+                [0,1,2,0]
+            ]
+        );
+        assert_eq!((gs.rows(), gs.cols(), gs.turn(), gs.placed_count()), (3, 4, Player::P1, 2));
+        let gs = play_col(0, &gs).unwrap();
+        assert_eq!((gs.turn(), gs.placed_count()), (Player::P2, 3));
+    }
+
+    #[test]
+    fn cells_come_with_their_coordinates() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0],
+                [0,2,0],
+                [1,1,2]
+            ]
+        );
+        let cells: Vec<(Move, Option<Player>)> = gs.cells().collect();
+        assert_eq!(cells.len(), 9);
+        assert_eq!(cells[0], (Move::new(0, 0), None));
+        let discs: Vec<(Move, Option<Player>)> = cells.into_iter().filter(|(_, disc)| disc.is_some()).collect();
+        assert_eq!(discs, vec![
+            (Move::new(1, 1), Some(Player::P2)),
+            (Move::new(2, 0), Some(Player::P1)),
+            (Move::new(2, 1), Some(Player::P1)),
+            (Move::new(2, 2), Some(Player::P2)),
+        ]);
+        assert!(gs.cells().all(|(mov, disc)| gs.disc_at(mov.row(), mov.col()) == disc));
+    }
+
+    #[test]
+    fn heights_follow_the_board() {
+        crate::rng::seed(283);
+        for plies in 0..43 {
+            let mut gs = get_random_position(&GameState::new(), plies);
+            assert_eq!(gs.heights, column_heights(&gs.board, &gs.blocked));
+            let moves = get_legal(&gs);
+            for mov in &moves {
+                make_move(*mov, &mut gs);
+            }
+            assert_eq!(gs.heights, column_heights(&gs.board, &gs.blocked));
+            for mov in moves.iter().rev() {
+                unmake_move(*mov, &mut gs);
+            }
+            assert_eq!(gs.heights, column_heights(&gs.board, &gs.blocked));
+        }
+        let gs = GameState::from_compact("7/7/7/7/3o3/2xx3 x").unwrap();
+        assert_eq!(gs.heights, vec![0, 0, 1, 2, 0, 0, 0]);
+    }
+
+    #[test]
+    fn blocks_break_lines() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,2,0,0,0],
+                [1,1,1,-1,1,2,2]
+            ]
+        );
+        assert_eq!(result(&gs), None);
+        assert!(winning_moves(&gs, Player::P1).is_empty());
+        assert!(threats(&gs, Player::P1).iter().all(|mov| mov.row != 5));
+        assert_eq!(try_play(Move::new(5, 3), &gs), Err(MoveError::Blocked));
+        // A block takes every line through it out of play: four along the bottom row, one up the
+        // column and two diagonals.
+        let open = GameState::new_from_board(vec2d![[0,0,0,0,0,0,0], [0,0,0,0,0,0,0], [0,0,0,0,0,0,0], [0,0,0,0,0,0,0], [0,0,0,0,0,0,0], [0,0,0,-1,0,0,0]]);
+        assert_eq!(potential_wins(&open).0, potential_wins(&GameState::new()).0 - 4 - 3);
+    }
+
+    #[test]
+    fn discs_pass_over_blocked_cells() {
+        let gs = GameState::try_from_board(
+            vec2d![
+                [0,-1,-1,0],
+                [0,-1,0,0],
+                [0,-1,-1,0],
+                [0,-1,0,0]
+            ]
+        ).unwrap();
+        assert!(gs.has_blocked_cells());
+        // Column 1 is blocked all the way up.
+        assert_eq!(get_legal(&gs).iter().map(|mov| mov.col()).collect::<Vec<_>>(), vec![0, 2, 3]);
+        assert!(play_col(1, &gs).is_none());
+        let mut gs = play_col(2, &gs).unwrap();
+        assert_eq!(gs.disc_at(3, 2), Some(Player::P1));
+        assert_eq!(Move::from_col(2, &gs), Some(Move::new(1, 2)));
+        make_move(Move::new(1, 2), &mut gs);
+        assert_eq!(Move::from_col(2, &gs), None);
+        unmake_move(Move::new(1, 2), &mut gs);
+        assert_eq!(gs.heights, column_heights(&gs.board, &gs.blocked));
+        assert_eq!(gs.mirrored().mirrored(), gs);
+        assert!(gs.mirrored().is_blocked(0, 2));
+        assert!(!no_wins_possible(&gs));
+        // A column shorter than the board fills up below its blocked top.
+        let short = GameState::try_from_board(vec2d![[-1,0], [0,0]]).unwrap();
+        assert!(play_col(0, &play_col(0, &short).unwrap()).is_none());
+        assert!(GameState::try_from_board(vec2d![[1,0], [-1,0], [0,0]]).is_err());
+        // Nor does a disc come to rest on a blocked cell with a gap below it.
+        let gap = GameState::new_from_board(vec2d![[0], [-1], [0]]);
+        assert_eq!(try_play(Move::new(0, 0), &gap), Err(MoveError::Floating));
+        assert_eq!(play_col(0, &gap), play(Move::new(2, 0), &gap));
+    }
+
+    #[test]
+    fn popping_removes_a_threat() {
+        let mut gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,2,2,2,0,0,0],
+                [0,1,2,1,1,0,1]
+            ]
+        );
+        assert_eq!(try_play_ply(Ply::Pop(4), &gs), Err(MoveError::CannotPop));
+        assert!(!get_legal_plies(&gs).contains(&Ply::Pop(4)));
+        gs.set_pop_out(true);
+        assert_eq!(winning_moves(&gs, Player::P2), vec![Move::new(4, 4)]);
+        assert_eq!(try_play_ply(Ply::Pop(2), &gs), Err(MoveError::CannotPop));
+        let plies = get_legal_plies(&gs);
+        assert_eq!(plies.len(), 7 + 4);
+        assert!(plies.contains(&Ply::Pop(4)) && !plies.contains(&Ply::Pop(2)));
+        let popped = try_play_ply(Ply::Pop(4), &gs).unwrap();
+        assert_eq!(popped.disc_at(5, 4), None);
+        assert_eq!(popped.turn, Player::P2);
+        assert!(winning_moves(&popped, Player::P2).is_empty());
+        assert_eq!(result(&popped), None);
+    }
+
+    #[test]
+    fn popping_can_hand_the_opponent_a_win() {
+        let mut gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [2,1,1,0,0,0,0],
+                [1,2,2,2,0,1,0]
+            ]
+        );
+        gs.set_pop_out(true);
+        let popped = try_play_ply(Ply::Pop(0), &gs).unwrap();
+        assert_eq!(popped.disc_at(5, 0), Some(Player::P2));
+        assert_eq!(popped.heights, column_heights(&popped.board, &popped.blocked));
+        assert_eq!(result(&popped), Some(GameResult::Win(Player::P2)));
+        // When a pop completes lines for both players, the player who popped wins.
+        let mut both = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [2,0,0,0,0,0,0],
+                [1,2,2,2,0,0,0],
+                [2,1,1,1,0,0,0]
+            ]
+        );
+        both.turn = Player::P2;
+        both.set_pop_out(true);
+        assert_eq!(result(&both), None);
+        let popped = try_play_ply(Ply::Pop(0), &both).unwrap();
+        assert_eq!(result(&popped), Some(GameResult::Win(Player::P2)));
+    }
+
+    #[test]
+    fn full_pop_out_boards_play_on() {
+        let mut gs = GameState::new_from_board(vec2d![[1,2,1],[1,1,2],[2,1,2]]);
+        gs.set_pop_out(true);
+        assert!(get_legal(&gs).is_empty());
+        assert_eq!(result(&gs), None);
+        assert_eq!(get_legal_plies(&gs), vec![Ply::Pop(1)]);
+        let popped = try_play_ply(Ply::Pop(1), &gs).unwrap();
+        assert_eq!(get_legal(&popped), vec![Move::new(0, 1)]);
+        // Without a disc of their own in the bottom row, the side to move is stuck.
+        gs.board[2][1] = Some(Player::P2);
+        let stuck = gs.clone();
+        gs.set_pop_out(false);
+        assert_eq!(result(&stuck.refreshed()), Some(GameResult::Draw));
+        assert_eq!(result(&gs.refreshed()), Some(GameResult::Draw));
+    }
+
+    #[test]
+    fn upward_gravity_stacks_from_the_top() {
+        let mut gs = GameState::new();
+        gs.set_gravity(GravityDir::Up);
+        assert_eq!(get_legal(&gs), (0..7).map(|col| Move::new(0, col)).collect::<Vec<_>>());
+        let gs = play_col(3, &play_col(3, &gs).unwrap()).unwrap();
+        assert_eq!((gs.disc_at(0, 3), gs.disc_at(1, 3)), (Some(Player::P1), Some(Player::P2)));
+        assert_eq!(get_legal(&gs)[3], Move::new(2, 3));
+        assert_eq!(try_play(Move::new(5, 0), &gs), Err(MoveError::Floating));
+        assert!(try_play(Move::new(2, 3), &gs).is_ok());
+        // Columns still mirror onto each other.
+        assert_eq!(play_col(1, &gs).unwrap().canonical(), play_col(5, &gs).unwrap().canonical());
+        let mut filled = gs.clone();
+        while let Some(mov) = get_legal(&filled).first().copied() {
+            make_move(mov, &mut filled);
+        }
+        assert!(filled.board.iter().flatten().all(|disc| disc.is_some()));
+    }
+
+    #[test]
+    fn sideways_gravity_fills_rows() {
+        let mut gs = GameState::new();
+        gs.set_gravity(GravityDir::Left);
+        assert_eq!(gs.lanes(), 6);
+        assert_eq!(get_legal(&gs), (0..6).map(|row| Move::new(row, 0)).collect::<Vec<_>>());
+        for lane in [0, 1, 0, 1, 0, 1] {
+            gs = play_col(lane, &gs).unwrap();
+        }
+        assert_eq!(Move::drop(&gs, 0), Some(Move::new(0, 3)));
+        assert_eq!(Move::drop(&gs, 6), None);
+        assert_eq!(try_play(Move::new(2, 3), &gs), Err(MoveError::Floating));
+        assert_eq!(gs.canonical(), gs);
+        gs = play_col(0, &gs).unwrap();
+        assert_eq!(result(&gs), Some(GameResult::Win(Player::P1)));
+        let mut right = GameState::new_with_size(2, 3);
+        right.set_gravity(GravityDir::Right);
+        assert_eq!(right.lane_of(Move::new(1, 2)), 1);
+        assert_eq!(get_legal(&right), vec![Move::new(0, 2), Move::new(1, 2)]);
+    }
+
+    #[test]
+    fn sideways_threats_are_reachable_along_their_row() {
+        let mut gs = GameState::new();
+        gs.set_gravity(GravityDir::Left);
+        for lane in [0, 1, 0, 1, 0, 2] {
+            gs = play_col(lane, &gs).unwrap();
+        }
+        assert_eq!(threats(&gs, Player::P1), vec![Move::new(0, 3)]);
+        assert_eq!(reachable_threats(&gs, Player::P1), vec![Move::new(0, 3)]);
+        assert_eq!(immediate_best(&gs), Some(Move::new(0, 3)));
+    }
+
+    #[test]
+    fn parses_ascii_diagrams() {
+        let diagram = "
+            .......
+            .......
+            .......
+            ...O...
+            ..XOX..
+            .XOXO..
+        ";
+        let expected = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,2,0,0,0],
+                [0,0,1,2,1,0,0],
+                [0,1,2,1,2,0,0]
+            ]
+        );
+        assert_eq!(GameState::from_ascii(diagram), Ok(expected.clone()));
+        assert_eq!(GameState::from_ascii(&diagram.replace('X', "1").replace('O', "2")), Ok(expected));
+        crate::rng::seed(284);
+        for plies in 0..30 {
+            let gs = get_random_position(&GameState::new_with_size(5, 11), plies);
+            assert_eq!(GameState::from_ascii(&gs.render(true)), Ok(gs));
+        }
+        let blocked = GameState::new_from_board(vec2d![[0,0,0,0],[0,-1,0,0],[1,2,-1,0]]);
+        for gs in [blocked.clone(), play_col(2, &play_col(1, &blocked).unwrap()).unwrap()] {
+            assert_eq!(GameState::from_ascii(&gs.render(true)), Ok(gs));
+        }
+    }
+
+    #[test]
+    fn ascii_diagrams_must_be_rectangular() {
+        assert!(GameState::from_ascii("....\n...\n").is_err());
+        assert!(GameState::from_ascii("....\n..?.\n").is_err());
+        assert!(GameState::from_ascii("").is_err());
+    }
+
+    #[test]
+    fn perft_counts() {
+        let gs = GameState::new();
+        let counts : Vec<u64> = (1..=5).map(|depth| perft(&gs, depth)).collect();
+        assert_eq!(counts, vec![7, 49, 343, 2401, 16807]);
+    }
+
+    /// The first wins come at ply 7, so from ply 8 on some lines stop short. Walking the 5.7
+    /// million positions is slow, so this only runs with `--ignored`.
+    #[test]
+    #[ignore]
+    fn perft_counts_past_the_first_wins() {
+        assert_eq!(perft(&GameState::new(), 8), 5_673_234);
+    }
+
+    #[test]
+    fn children_match_play() {
+        crate::rng::seed(272);
+        for plies in 0..42 {
+            let gs = get_random_position(&GameState::new(), plies);
+            assert_eq!(children(&gs).count(), get_legal(&gs).len());
+            for (mov, child) in children(&gs) {
+                assert_eq!(Some(child), play(mov, &gs));
+            }
+        }
+    }
+
+    #[test]
+    fn disabled_columns_are_not_playable() {
+        let mut gs = GameState::new();
+        gs.disable_column(3);
+        assert!(get_legal(&gs).iter().all(|mov| mov.col() != 3));
+        assert_eq!(get_legal(&gs).len(), 6);
+        assert_eq!(try_play(Move::new(5, 3), &gs), Err(MoveError::ColumnDisabled));
+        assert_eq!(try_play(Move::new(4, 2), &gs), Err(MoveError::Floating));
+        assert_eq!(try_play(Move::new(5, 7), &gs), Err(MoveError::OffBoard));
+        gs.enable_column(3);
+        assert_eq!(get_legal(&gs).len(), 7);
+        let played = try_play(Move::new(5, 3), &gs).unwrap();
+        assert_eq!(try_play(Move::new(5, 3), &played), Err(MoveError::Occupied));
+    }
+
+    #[test]
+    fn board_has_requested_size() {
+        for (rows, cols) in [(5, 10), (8, 8)] {
+            let gs = GameState::new_with_size(rows, cols);
+            assert_eq!((gs.rows, gs.cols), (rows, cols));
+            assert_eq!(gs.board.len(), rows);
+            assert_eq!(gs.board[0].len(), cols);
+            assert_eq!(get_legal(&gs).len(), cols);
+            assert_eq!(all_lines(&gs).count(), rows * (cols - 3) + (rows - 3) * cols + 2 * (rows - 3) * (cols - 3));
+        }
+    }
+
+    #[test]
+    fn play_col_agrees_with_play() {
+        crate::rng::seed(259);
+        for plies in 0..40 {
+            let gs = get_random_position(&GameState::new(), plies);
+            for col in 0..=gs.cols {
+                match Move::from_col(col, &gs) {
+                    Some(mov) => assert_eq!(play_col(col, &gs), play(mov, &gs)),
+                    None => assert_eq!(play_col(col, &gs), None),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compact_notation_round_trip() {
+        crate::rng::seed(260);
+        assert_eq!(play_col(3, &GameState::new()).unwrap().to_string_compact(), "7/7/7/7/7/3x3 o");
+        for i in 0..1000 {
+            let size = [(6, 7), (5, 10), (8, 8), (4, 12)][i % 4];
+            let gs = get_random_position(&GameState::new_with_size(size.0, size.1), i % 40);
+            assert_eq!(GameState::from_compact(&gs.to_string_compact()), Ok(gs));
+        }
+        let mut gs = GameState::new_from_board(vec2d![[0,0,0,0],[0,-1,0,0],[2,1,0,-1]]);
+        gs.disable_column(2);
+        let gs = GameState { win_len : 3, ..gs };
+        assert_eq!(gs.to_string_compact(), "4/1#2/ox1# x w3 d2");
+        assert_eq!(GameState::from_compact("4/1#2/ox1# x w3 d2"), Ok(gs));
+        let mut sideways = GameState::new_with_size(3, 4);
+        sideways.set_gravity(GravityDir::Left);
+        sideways.set_pop_out(true);
+        sideways.disable_column(1);
+        let sideways = play_col(0, &sideways).unwrap();
+        assert_eq!(sideways.to_string_compact(), "x3/4/4 o left pop d1");
+        assert_eq!(GameState::from_compact("x3/4/4 o left pop d1"), Ok(sideways));
+    }
+
+    #[test]
+    fn compact_notation_rejects_malformed() {
+        for compact in ["", "7/7/7", "7/7/7 z", "7/6/7 x", "3q3 x", "0x6 x", "x6/7 o", "/ x", "7/8 x", "7/x18446744073709551615 x", "99999999999 x", "1025 x",
+                "7 x w0", "7 x wide", "7 x d", "7 x d7", "4/4 x left d2", "7 x fast", "#3/4 x pop", "x/#/1 x", "x1/#1 x right"] {
+            assert!(matches!(GameState::from_compact(compact), Err(ParseError(_))), "{}", compact);
+        }
+    }
+
+    #[test]
+    fn try_from_board_rejects_impossible_boards() {
+        let gs = GameState::try_from_board(vec2d![[0,0,0],[0,2,0],[0,1,1]]).unwrap();
+        assert_eq!(gs.turn, Player::P2);
+        assert_eq!(GameState::try_from_board(vec![]), Err(BoardError::Empty));
+        assert_eq!(GameState::try_from_board(vec2d![[0,0,0],[0,0]]), Err(BoardError::NotRectangular));
+        assert_eq!(GameState::try_from_board(vec2d![[0,0,0],[0,3,0]]), Err(BoardError::UnknownCell(3)));
+        assert_eq!(GameState::try_from_board(vec2d![[0,1,0],[0,0,2]]), Err(BoardError::FloatingDisc(Move::new(0, 1))));
+        assert_eq!(GameState::try_from_board(vec2d![[0,1,0],[0,1,1]]), Err(BoardError::DiscCounts(3, 0)));
+    }
+
+    #[test]
+    fn boards_too_small_for_diagonals() {
+        for (rows, cols) in [(3, 3), (6, 3), (2, 7)] {
+            let mut gs = GameState::new_with_size(rows, cols);
+            assert_eq!(all_lines(&gs).filter(|line| line[0].row() != line[1].row() && line[0].col() != line[1].col()).count(), 0);
+            for ply in 0..rows * cols {
+                assert_eq!(result(&gs), None);
+                // Cycling through columns with a stride of 2 never lines up four of a kind here.
+                let col = (ply * 2 + ply / cols) % cols;
+                gs = play_col(col, &gs).unwrap();
+            }
+            assert_eq!(result(&gs), Some(GameResult::Draw), "{}", gs);
+        }
+    }
+
+    #[test]
+    fn center_out_orders() {
+        assert_eq!(center_out(7), vec![3, 2, 4, 1, 5, 0, 6]);
+        assert_eq!(center_out(6), vec![2, 3, 1, 4, 0, 5]);
+        assert_eq!(center_out(1), vec![0]);
+    }
+
+    #[test]
+    fn win_length_is_configurable() {
+        let mut connect_3 = GameState::new_with_win_len(4, 5, 3);
+        for col in [0, 0, 1, 1] {
+            connect_3 = play_col(col, &connect_3).unwrap();
+            assert_eq!(result(&connect_3), None);
+        }
+        assert_eq!(winning_moves(&connect_3, Player::P1), vec![Move::new(3, 2)]);
+        connect_3 = play_col(2, &connect_3).unwrap();
+        assert_eq!(result(&connect_3), Some(GameResult::Win(Player::P1)));
+        assert_eq!(winning_line(&connect_3), Some((Player::P1, vec![Move::new(3, 0), Move::new(3, 1), Move::new(3, 2)])));
+
+        let mut connect_5 = GameState::new_with_win_len(6, 7, 5);
+        for col in [0, 0, 1, 1, 2, 2, 3, 3] {
+            connect_5 = play_col(col, &connect_5).unwrap();
+        }
+        assert_eq!(result(&connect_5), None);
+        connect_5 = play_col(4, &connect_5).unwrap();
+        assert_eq!(result(&connect_5), Some(GameResult::Win(Player::P1)));
+        assert_eq!(all_lines(&connect_5).count(), 6 * 3 + 2 * 7 + 2 * 2 * 3);
+    }
 }
@@ -0,0 +1,19 @@
+#[macro_use]
+pub mod game_logic;
+pub mod game;
+pub mod evaluator;
+pub mod bitboard;
+pub mod rng;
+pub mod transposition;
+pub mod solve;
+pub mod record;
+pub mod mcts;
+pub mod negamax;
+pub mod tournament;
+pub mod rating;
+pub mod book;
+pub mod puzzles;
+pub mod cli;
+pub mod selfplay;
+#[cfg(feature = "ffi")]
+pub mod ffi;
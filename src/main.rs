@@ -1,10 +1,59 @@
-use crate::game::Game;
-use crate::game_logic::{GameState, get_legal, play, result};
+use std::io::{self, Read};
+use std::process;
+use four_in_a_row::cli::{analyze_board, parse_args};
+use four_in_a_row::game::Game;
+use four_in_a_row::rng;
 
-mod game_logic;
-mod game;
+/// How deep `analyze` searches unless `--depth` says otherwise.
+const ANALYSIS_DEPTH: i32 = 8;
 
+const USAGE: &str = "usage: four-in-a-row [--rows N] [--cols N] [--p1 AGENT] [--p2 AGENT] [--depth N] [--time SECONDS] [--seed N]
+       four-in-a-row analyze [--depth N] < board
+AGENT is one of human, random, minmax or mcts.";
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    process::exit(2);
+}
+
+/// `analyze` reads a board from stdin, in compact notation or as a diagram, and prints the score
+/// of every column. Otherwise a game is played, set up from the flags when both players are given
+/// and through the interactive prompts when not.
 fn main() {
-    let mut game = Game::new();
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let analyze = args.first().map(String::as_str) == Some("analyze");
+    if analyze {
+        args.remove(0);
+    }
+    let config = parse_args(args).unwrap_or_else(|error| fail(&error.to_string()));
+    if let Some(seed) = config.seed {
+        rng::seed(seed);
+    }
+    if analyze {
+        let mut board = String::new();
+        if let Err(error) = io::stdin().read_to_string(&mut board) {
+            eprintln!("could not read the board: {}", error);
+            process::exit(1);
+        }
+        match analyze_board(&board, config.depth.unwrap_or(ANALYSIS_DEPTH)) {
+            Ok(analysis) => println!("{}", analysis),
+            Err(error) => {
+                eprintln!("could not parse the board: {}", error);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    if let Err(error) = config.check_game() {
+        fail(&error.to_string());
+    }
+    let mut game = match (config.p1, config.p2) {
+        (Some(p1), Some(p2)) => {
+            let mut game = Game::new_with_agents(config.rows, config.cols, config.agent(p1), config.agent(p2));
+            game.set_render(true);
+            game
+        }
+        _ => Game::new_interactive(config.rows, config.cols),
+    };
     game.start_game();
 }
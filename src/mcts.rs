@@ -0,0 +1,224 @@
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+use crate::game::Agent;
+use crate::game_logic::{get_legal, has_legal_move, next_turn, play, result, winning_moves, GameResult, GameState, Move};
+use crate::rng::with_rng;
+
+/// Picks the moves played out during a rollout.
+pub trait RolloutPolicy: Send {
+    fn choose(&self, gs: &GameState, rng: &mut dyn RngCore) -> Move;
+}
+
+/// Uniformly random rollouts.
+pub struct RandomRollout;
+
+impl RolloutPolicy for RandomRollout {
+    fn choose(&self, gs: &GameState, rng: &mut dyn RngCore) -> Move {
+        let moves = get_legal(gs);
+        moves[rng.gen_range(0..moves.len())]
+    }
+}
+
+/// Takes an immediate win, otherwise blocks the opponent's immediate win, otherwise plays a
+/// random move weighted towards the center columns.
+pub struct InformedRollout;
+
+impl RolloutPolicy for InformedRollout {
+    fn choose(&self, gs: &GameState, rng: &mut dyn RngCore) -> Move {
+        if let Some(mov) = winning_moves(gs, gs.turn).first() {
+            return *mov;
+        }
+        if let Some(mov) = winning_moves(gs, next_turn(gs.turn)).first() {
+            return *mov;
+        }
+        let moves = get_legal(gs);
+        let center = (gs.cols - 1) as f32 / 2.0;
+        let weights = moves.iter().map(|mov| 1.0 + center - (mov.col() as f32 - center).abs());
+        moves[WeightedIndex::new(weights).unwrap().sample(rng)]
+    }
+}
+
+pub const DEFAULT_ITERATIONS: usize = 2000;
+
+struct Node {
+    state: GameState,
+    mov: Option<Move>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<Move>,
+    visits: u32,
+    /// Accumulated reward for the player who made the move into this node.
+    reward: f32,
+}
+
+impl Node {
+    fn new(state: GameState, mov: Option<Move>, parent: Option<usize>) -> Self {
+        let untried = if result(&state).is_some() { Vec::new() } else { get_legal(&state) };
+        Self {
+            state,
+            mov,
+            parent,
+            children: Vec::new(),
+            untried,
+            visits: 0,
+            reward: 0.0,
+        }
+    }
+}
+
+pub struct MctsAgent {
+    iterations: usize,
+    exploration: f32,
+    rollout_policy: Box<dyn RolloutPolicy>,
+}
+
+impl MctsAgent {
+    pub fn new(iterations: usize) -> Self {
+        Self {
+            iterations,
+            exploration: std::f32::consts::SQRT_2,
+            rollout_policy: Box::new(InformedRollout),
+        }
+    }
+
+    pub fn with_rollout_policy(mut self, rollout_policy: Box<dyn RolloutPolicy>) -> Self {
+        self.rollout_policy = rollout_policy;
+        self
+    }
+
+    /// Plays `gs` out to the end with the rollout policy. A rollout that runs out of open columns
+    /// is a draw.
+    pub fn rollout(&self, gs: &GameState) -> GameResult {
+        let mut current = gs.clone();
+        loop {
+            if let Some(res) = result(&current) {
+                return res;
+            }
+            if !has_legal_move(&current) {
+                return GameResult::Draw;
+            }
+            let mov = with_rng(|rng| self.rollout_policy.choose(&current, rng));
+            current = play(mov, &current).unwrap();
+        }
+    }
+
+    fn select_child(&self, tree: &[Node], node: usize) -> usize {
+        let parent_visits = tree[node].visits as f32;
+        let uct = |child: &Node| child.reward / child.visits as f32
+            + self.exploration * (parent_visits.ln() / child.visits as f32).sqrt();
+        *tree[node].children.iter()
+            .max_by(|a, b| uct(&tree[**a]).total_cmp(&uct(&tree[**b])))
+            .unwrap()
+    }
+}
+
+impl Agent for MctsAgent {
+    fn label(&self) -> String {
+        format!("mcts-{}", self.iterations)
+    }
+
+    fn next_move(&mut self, gs: &GameState) -> Move {
+        let mut tree = vec![Node::new(gs.clone(), None, None)];
+        for _ in 0..self.iterations {
+            let mut node = 0;
+            while tree[node].untried.is_empty() && !tree[node].children.is_empty() {
+                node = self.select_child(&tree, node);
+            }
+            if !tree[node].untried.is_empty() {
+                let index = with_rng(|rng| rng.gen_range(0..tree[node].untried.len()));
+                let mov = tree[node].untried.swap_remove(index);
+                let child = Node::new(play(mov, &tree[node].state).unwrap(), Some(mov), Some(node));
+                tree.push(child);
+                let child_index = tree.len() - 1;
+                tree[node].children.push(child_index);
+                node = child_index;
+            }
+            let outcome = self.rollout(&tree[node].state);
+            let mut current = Some(node);
+            while let Some(index) = current {
+                let mover = next_turn(tree[index].state.turn);
+                tree[index].visits += 1;
+                tree[index].reward += outcome.score_for(mover);
+                current = tree[index].parent;
+            }
+        }
+        // Without iterations, or on a finished board, nothing was expanded, so any legal move will do.
+        match tree[0].children.iter().max_by_key(|child| tree[**child].visits) {
+            Some(best) => tree[*best].mov.unwrap(),
+            None => with_rng(|rng| self.rollout_policy.choose(gs, rng)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::Agent;
+    use crate::game_logic::{play, result, GameResult, GameState, Player};
+    use crate::mcts::{InformedRollout, MctsAgent, RandomRollout, RolloutPolicy};
+    use crate::rng::{reset_rng, seed, with_rng};
+
+    #[test]
+    fn informed_rollout_takes_mate_in_one() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,2,0,0,0,0],
+                [0,0,2,0,0,0,0],
+                [0,0,2,1,1,1,0]
+            ]
+        );
+        seed(215);
+        let agent = MctsAgent::new(1);
+        for _ in 0..20 {
+            assert_eq!(agent.rollout(&gs), GameResult::Win(Player::P1));
+        }
+        reset_rng();
+    }
+
+    #[test]
+    fn plays_a_legal_move_without_a_tree() {
+        let won = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,2,0,0,0,0],
+                [0,0,2,0,0,0,0],
+                [0,0,2,0,0,0,0],
+                [0,1,2,1,1,1,0]
+            ]
+        );
+        for (mut agent, gs) in [(MctsAgent::new(0), GameState::new()), (MctsAgent::new(50), won)] {
+            assert!(play(agent.next_move(&gs), &gs).is_some());
+        }
+    }
+
+    #[test]
+    fn informed_rollouts_beat_random_rollouts() {
+        seed(215);
+        let (mut informed_wins, mut random_wins) = (0, 0);
+        for game in 0..100 {
+            let informed_player = if game % 2 == 0 { Player::P1 } else { Player::P2 };
+            let mut gs = GameState::new();
+            let outcome = loop {
+                if let Some(res) = result(&gs) {
+                    break res;
+                }
+                let mov = if gs.turn == informed_player {
+                    with_rng(|rng| InformedRollout.choose(&gs, rng))
+                } else {
+                    with_rng(|rng| RandomRollout.choose(&gs, rng))
+                };
+                gs = play(mov, &gs).unwrap();
+            };
+            match outcome {
+                GameResult::Win(winner) if winner == informed_player => informed_wins += 1,
+                GameResult::Win(_) => random_wins += 1,
+                GameResult::Draw => {}
+            }
+        }
+        reset_rng();
+        assert!(informed_wins > 4 * random_wins, "{} vs {}", informed_wins, random_wins);
+    }
+}
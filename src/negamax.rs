@@ -0,0 +1,150 @@
+use crate::evaluator::WindowEvaluator;
+use crate::game::{leaf_value, search_moves, to_parent, Agent, Score, DEFAULT_DEPTH};
+use crate::game_logic::{disc_count, has_legal_move, make_move, result, unmake_move, GameState, Move, Player};
+use crate::transposition::{Bound, TranspositionTable};
+
+/// +1 if P1 is to move and -1 otherwise, turning P1-perspective values into side-to-move ones.
+fn side(gs: &GameState) -> f32 {
+    match gs.turn() {
+        Player::P1 => 1.0,
+        Player::P2 => -1.0,
+    }
+}
+
+/// The same search as `MinMaxAgent`, written as negamax: every value is from the side to move's
+/// perspective and flips sign from one ply to the next, so there is only one branch to get right.
+pub struct NegaMaxAgent {
+    depth: i32,
+    symmetry_pruning: bool,
+    table: TranspositionTable,
+}
+
+impl Default for NegaMaxAgent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NegaMaxAgent {
+    pub fn new() -> Self {
+        Self::new_with_depth(DEFAULT_DEPTH)
+    }
+
+    pub fn new_with_depth(depth: i32) -> Self {
+        Self {
+            depth,
+            symmetry_pruning: true,
+            table: TranspositionTable::new(),
+        }
+    }
+
+    pub fn with_symmetry_pruning(mut self, symmetry_pruning: bool) -> Self {
+        self.symmetry_pruning = symmetry_pruning;
+        self
+    }
+
+    fn nega_max(&self, gs: &mut GameState, depth: i32, mut alpha: f32, mut beta: f32, table: &mut TranspositionTable) -> f32 {
+        if depth == 0 || result(gs).is_some() || !has_legal_move(gs) {
+            return side(gs) * leaf_value(gs, &WindowEvaluator);
+        }
+        // Mirrored positions have the same value, so they share an entry.
+        let (key, packed) = gs.canonical_key();
+        if let Some(entry) = table.probe_packed(key, &packed) {
+            if entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact(value) => return value,
+                    Bound::LowerBound(value) => alpha = alpha.max(value),
+                    Bound::UpperBound(value) => beta = beta.min(value),
+                }
+                if alpha >= beta {
+                    return entry.bound.value();
+                }
+            }
+        }
+        let alpha_orig = alpha;
+        let mut best = f32::NEG_INFINITY;
+        for mov in search_moves(gs, self.symmetry_pruning) {
+            make_move(mov, gs);
+            let value = -to_parent(self.nega_max(gs, depth - 1, -beta, -alpha, table));
+            unmake_move(mov, gs);
+            best = best.max(value);
+            alpha = alpha.max(value);
+            if alpha >= beta {
+                break;
+            }
+        }
+        let bound = if best <= alpha_orig {
+            Bound::UpperBound(best)
+        } else if best >= beta {
+            Bound::LowerBound(best)
+        } else {
+            Bound::Exact(best)
+        };
+        table.store_packed(key, packed, disc_count(gs), depth, bound);
+        best
+    }
+
+    fn search(&self, gs: &GameState, depth: i32, table: &mut TranspositionTable) -> (Move, f32) {
+        let moves = search_moves(gs, self.symmetry_pruning);
+        let mut best = (moves[0], f32::NEG_INFINITY);
+        let mut state = gs.clone();
+        for mov in moves {
+            make_move(mov, &mut state);
+            let value = -to_parent(self.nega_max(&mut state, depth - 1, f32::NEG_INFINITY, -best.1, table));
+            unmake_move(mov, &mut state);
+            if value > best.1 {
+                best = (mov, value);
+            }
+        }
+        best
+    }
+
+    /// Searches with iterative deepening up to the agent's depth, keeping the table between moves
+    /// like `MinMaxAgent` does.
+    pub fn next_move_with_score(&mut self, gs: &GameState) -> (Move, Score) {
+        let mut table = std::mem::take(&mut self.table);
+        table.retain_reachable(gs);
+        let mut best = (Move::new(0, 0), f32::NEG_INFINITY);
+        for depth in 1..=self.depth {
+            best = self.search(gs, depth, &mut table);
+        }
+        self.table = table;
+        (best.0, Score::from_value(side(gs) * best.1, gs.turn()))
+    }
+}
+
+impl Agent for NegaMaxAgent {
+    fn label(&self) -> String {
+        format!("negamax-d{}", self.depth)
+    }
+
+    fn next_move(&mut self, gs: &GameState) -> Move {
+        self.next_move_with_score(gs).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::MinMaxAgent;
+    use crate::game_logic::{get_random_position, result, GameState};
+    use crate::negamax::NegaMaxAgent;
+    use crate::rng;
+
+    #[test]
+    fn agrees_with_min_max() {
+        rng::seed(257);
+        let mut min_max = MinMaxAgent::new_with_depth(6);
+        let mut nega_max = NegaMaxAgent::new_with_depth(6);
+        let mut checked = 0;
+        while checked < 50 {
+            let gs = get_random_position(&GameState::new(), checked % 30);
+            if result(&gs).is_some() {
+                continue;
+            }
+            let (_, min_max_score, _) = min_max.next_move_with_stats(&gs);
+            let (_, nega_max_score) = nega_max.next_move_with_score(&gs);
+            assert_eq!(min_max_score, nega_max_score, "{}", gs);
+            checked += 1;
+        }
+    }
+}
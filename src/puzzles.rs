@@ -0,0 +1,83 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use crate::game_logic::{children, get_legal, play, result, GameResult, GameState, Move};
+use crate::solve::solve_position;
+
+/// Positions are tried this many times over for each puzzle asked for before giving up.
+const ATTEMPTS_PER_PUZZLE: usize = 1000;
+
+/// A position the side to move wins by force in exactly `plies` plies, starting with `solution`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Puzzle {
+    pub position: GameState,
+    pub solution: Move,
+    pub plies: u32,
+}
+
+/// A random unfinished position at least half full, where the solver is quick.
+fn random_late_position(rows: usize, cols: usize, rng: &mut ChaCha8Rng) -> GameState {
+    let mut gs = GameState::new_with_size(rows, cols);
+    let cells = rows * cols;
+    for _ in 0..rng.gen_range(cells / 2..cells.max(1)) {
+        let moves = get_legal(&gs);
+        let next = play(moves[rng.gen_range(0..moves.len())], &gs).unwrap();
+        if result(&next).is_some() {
+            break;
+        }
+        gs = next;
+    }
+    gs
+}
+
+/// Up to `count` puzzles on a `rows` x `cols` board that are won in exactly `plies` plies, the
+/// same ones for the same `seed`. The winner makes the first and last move, so there are none for
+/// an even `plies`. Fewer puzzles come back when not enough turn up among the positions tried.
+pub fn generate(rows: usize, cols: usize, seed: u64, count: usize, plies: u32) -> Vec<Puzzle> {
+    if plies.is_multiple_of(2) {
+        return Vec::new();
+    }
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut puzzles: Vec<Puzzle> = Vec::new();
+    for _ in 0..count * ATTEMPTS_PER_PUZZLE {
+        if puzzles.len() == count {
+            break;
+        }
+        let gs = random_late_position(rows, cols, &mut rng);
+        if solve_position(&gs) != (GameResult::Win(gs.turn()), plies) || puzzles.iter().any(|puzzle| puzzle.position == gs) {
+            continue;
+        }
+        let solution = children(&gs)
+            .find(|(_, child)| solve_position(child) == (GameResult::Win(gs.turn()), plies - 1))
+            .map(|(mov, _)| mov)
+            .unwrap();
+        puzzles.push(Puzzle { position: gs, solution, plies });
+    }
+    puzzles
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game_logic::{play, GameResult};
+    use crate::puzzles::generate;
+    use crate::solve::solve_position;
+
+    #[test]
+    fn solutions_keep_the_win() {
+        let puzzles = generate(6, 7, 316, 5, 3);
+        assert_eq!(puzzles.len(), 5);
+        for puzzle in &puzzles {
+            let winner = puzzle.position.turn();
+            assert_eq!(solve_position(&puzzle.position), (GameResult::Win(winner), 3), "{}", puzzle.position);
+            let after = play(puzzle.solution, &puzzle.position).unwrap();
+            assert_eq!(solve_position(&after), (GameResult::Win(winner), 2), "{}", puzzle.position);
+        }
+        assert_eq!(generate(6, 7, 316, 5, 3), puzzles);
+    }
+
+    #[test]
+    fn impossible_requests_find_nothing() {
+        assert!(generate(6, 7, 316, 5, 2).is_empty());
+        assert!(generate(6, 7, 316, 5, 0).is_empty());
+        assert!(generate(1, 1, 316, 1, 1).is_empty());
+    }
+}
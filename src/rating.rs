@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use crate::tournament::{AgentId, MatchResult};
+
+const ITERATIONS: usize = 200;
+/// How far a rating moves per game of surprise in each iteration.
+const STEP: f64 = 8.0;
+
+/// The score `a` is expected to make per game against `b`, on the Elo scale.
+fn expected(a: f64, b: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((b - a) / 400.0))
+}
+
+/// Relative Elo ratings for every agent in `results`, averaging 0. Each iteration moves every
+/// rating towards the one that would have predicted its scores, so agents that never met are
+/// compared through the opponents they share. An agent that won or lost every game has no finite
+/// rating, and ends up far above or below the rest.
+pub fn estimate(results: &[MatchResult]) -> HashMap<AgentId, f64> {
+    let mut ratings: HashMap<AgentId, f64> = HashMap::new();
+    for result in results {
+        ratings.entry(result.a.clone()).or_insert(0.0);
+        ratings.entry(result.b.clone()).or_insert(0.0);
+    }
+    for _ in 0..ITERATIONS {
+        let mut updates: HashMap<&AgentId, f64> = HashMap::new();
+        for result in results.iter().filter(|result| result.games() > 0) {
+            let games = result.games() as f64;
+            let score = (result.a_wins as f64 + 0.5 * result.draws as f64) / games;
+            let surprise = games * (score - expected(ratings[&result.a], ratings[&result.b]));
+            *updates.entry(&result.a).or_insert(0.0) += STEP * surprise;
+            *updates.entry(&result.b).or_insert(0.0) -= STEP * surprise;
+        }
+        for (id, update) in updates {
+            *ratings.get_mut(id).unwrap() += update;
+        }
+    }
+    let mean = ratings.values().sum::<f64>() / ratings.len().max(1) as f64;
+    ratings.values_mut().for_each(|rating| *rating -= mean);
+    ratings
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rating::estimate;
+    use crate::tournament::{AgentId, MatchResult};
+
+    fn result(a: &str, b: &str, a_wins: usize, b_wins: usize, draws: usize) -> MatchResult {
+        MatchResult { a: AgentId(a.to_string()), b: AgentId(b.to_string()), a_wins, b_wins, draws }
+    }
+
+    #[test]
+    fn dominant_agent_rates_highest() {
+        let ratings = estimate(&[
+            result("minmax-d6", "minmax-d2", 9, 0, 1),
+            result("random", "minmax-d6", 0, 10, 0),
+            result("minmax-d2", "random", 8, 1, 1),
+        ]);
+        let rating = |label: &str| ratings[&AgentId(label.to_string())];
+        assert!(rating("minmax-d6") > rating("minmax-d2"));
+        assert!(rating("minmax-d2") > rating("random"));
+        assert!(ratings.values().sum::<f64>().abs() < 1e-6);
+    }
+
+    #[test]
+    fn even_results_give_even_ratings() {
+        let ratings = estimate(&[result("a", "b", 3, 3, 4)]);
+        assert!((ratings[&AgentId("a".to_string())] - ratings[&AgentId("b".to_string())]).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,203 @@
+use std::fmt::Write;
+use crate::game_logic::{GameResult, GameState, Player};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedMove {
+    pub col: usize,
+    pub eval: Option<f32>,
+}
+
+/// A finished or ongoing game with enough metadata to archive it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameRecord {
+    pub rows: usize,
+    pub cols: usize,
+    /// The position the game started from, when it isn't an empty board under the standard
+    /// rules. It is kept in a `Setup` header in compact notation, which carries the rules along.
+    pub start: Option<GameState>,
+    pub player_1: String,
+    pub player_2: String,
+    pub result: Option<GameResult>,
+    pub date: Option<String>,
+    pub moves: Vec<RecordedMove>,
+}
+
+fn result_tag(result: Option<GameResult>) -> &'static str {
+    match result {
+        Some(GameResult::Win(Player::P1)) => "1-0",
+        Some(GameResult::Win(Player::P2)) => "0-1",
+        Some(GameResult::Draw) => "1/2-1/2",
+        None => "*",
+    }
+}
+
+fn parse_result_tag(tag: &str) -> Result<Option<GameResult>, String> {
+    match tag {
+        "1-0" => Ok(Some(GameResult::Win(Player::P1))),
+        "0-1" => Ok(Some(GameResult::Win(Player::P2))),
+        "1/2-1/2" => Ok(Some(GameResult::Draw)),
+        "*" => Ok(None),
+        other => Err(format!("unknown result '{}'", other)),
+    }
+}
+
+/// Writes a PGN-style text: a tag-pair header followed by the numbered column moves, with
+/// evaluations as `{...}` comments.
+pub fn to_pgn(record: &GameRecord) -> String {
+    let mut pgn = String::new();
+    writeln!(pgn, "[Rows \"{}\"]", record.rows).unwrap();
+    writeln!(pgn, "[Cols \"{}\"]", record.cols).unwrap();
+    if let Some(start) = &record.start {
+        writeln!(pgn, "[Setup \"{}\"]", start.to_string_compact()).unwrap();
+    }
+    writeln!(pgn, "[Player1 \"{}\"]", record.player_1).unwrap();
+    writeln!(pgn, "[Player2 \"{}\"]", record.player_2).unwrap();
+    writeln!(pgn, "[Result \"{}\"]", result_tag(record.result)).unwrap();
+    if let Some(date) = &record.date {
+        writeln!(pgn, "[Date \"{}\"]", date).unwrap();
+    }
+    pgn.push('\n');
+    let mut tokens = Vec::new();
+    for (i, mov) in record.moves.iter().enumerate() {
+        if i % 2 == 0 {
+            tokens.push(format!("{}.", i / 2 + 1));
+        }
+        tokens.push(mov.col.to_string());
+        if let Some(eval) = mov.eval {
+            tokens.push(format!("{{{}}}", eval));
+        }
+    }
+    tokens.push(result_tag(record.result).to_string());
+    pgn.push_str(&tokens.join(" "));
+    pgn.push('\n');
+    pgn
+}
+
+fn parse_header(line: &str) -> Result<(&str, &str), String> {
+    let inner = line.strip_prefix('[').and_then(|l| l.strip_suffix(']'))
+        .ok_or_else(|| format!("malformed header line '{}'", line))?;
+    let (key, value) = inner.split_once(' ')
+        .ok_or_else(|| format!("malformed header line '{}'", line))?;
+    let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"'))
+        .ok_or_else(|| format!("header value for {} must be quoted", key))?;
+    Ok((key, value))
+}
+
+pub fn from_pgn(s: &str) -> Result<GameRecord, String> {
+    let (mut rows, mut cols, mut result) = (None, None, None);
+    let mut record = GameRecord {
+        rows: 0,
+        cols: 0,
+        start: None,
+        player_1: String::new(),
+        player_2: String::new(),
+        result: None,
+        date: None,
+        moves: Vec::new(),
+    };
+    let mut lines = s.lines().map(str::trim).skip_while(|line| line.is_empty()).peekable();
+    while let Some(line) = lines.next_if(|line| line.starts_with('[')) {
+        let (key, value) = parse_header(line)?;
+        let parse_size = |value: &str| value.parse::<usize>().map_err(|_| format!("invalid {} '{}'", key, value));
+        match key {
+            "Rows" => rows = Some(parse_size(value)?),
+            "Cols" => cols = Some(parse_size(value)?),
+            "Setup" => record.start = Some(GameState::from_compact(value).map_err(|err| format!("invalid Setup: {}", err))?),
+            "Player1" => record.player_1 = value.to_string(),
+            "Player2" => record.player_2 = value.to_string(),
+            "Result" => result = Some(parse_result_tag(value)?),
+            "Date" => record.date = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    record.rows = rows.ok_or("missing Rows header")?;
+    record.cols = cols.ok_or("missing Cols header")?;
+    record.result = result.ok_or("missing Result header")?;
+    if record.start.as_ref().is_some_and(|start| (start.rows(), start.cols()) != (record.rows, record.cols)) {
+        return Err("the Setup position does not match Rows and Cols".to_string());
+    }
+
+    let movetext = lines.collect::<Vec<&str>>().join(" ");
+    let mut rest = movetext.as_str();
+    while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix('{') {
+            let end = comment.find('}').ok_or("unterminated comment")?;
+            let eval = comment[..end].trim().parse::<f32>().map_err(|_| format!("invalid evaluation '{}'", &comment[..end]))?;
+            record.moves.last_mut().ok_or("comment before the first move")?.eval = Some(eval);
+            rest = &comment[end + 1..];
+            continue;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let token = &rest[..end];
+        rest = &rest[end..];
+        if token.ends_with('.') && token[..token.len() - 1].parse::<usize>().is_ok() {
+            continue;
+        }
+        if let Ok(result) = parse_result_tag(token) {
+            if result != record.result {
+                return Err(format!("movetext result '{}' does not match the header", token));
+            }
+            continue;
+        }
+        let col = token.parse::<usize>().map_err(|_| format!("invalid move '{}'", token))?;
+        if col >= record.cols {
+            return Err(format!("column {} is off a board with {} columns", col, record.cols));
+        }
+        record.moves.push(RecordedMove { col, eval: None });
+    }
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game_logic::{GameResult, GameState, Player};
+    use crate::record::{from_pgn, to_pgn, GameRecord, RecordedMove};
+
+    #[test]
+    fn pgn_round_trip() {
+        let record = GameRecord {
+            rows: 6,
+            cols: 7,
+            start: None,
+            player_1: "MinMaxAgent".to_string(),
+            player_2: "RandomMover".to_string(),
+            result: Some(GameResult::Win(Player::P2)),
+            date: Some("2024.05.01".to_string()),
+            moves: [3, 3, 4, 2, 5, 6, 0].into_iter().enumerate()
+                .map(|(i, col)| RecordedMove { col, eval: if i == 1 { Some(-1.5) } else { None } })
+                .collect(),
+        };
+        let pgn = to_pgn(&record);
+        assert!(pgn.contains("1. 3 3 {-1.5} 2. 4 2"));
+        assert_eq!(from_pgn(&pgn), Ok(record));
+    }
+
+    #[test]
+    fn pgn_keeps_the_rules() {
+        let start = GameState::from_compact("#4/5/x4 o w3 d4").unwrap();
+        let record = GameRecord {
+            rows: 3,
+            cols: 5,
+            start: Some(start),
+            player_1: "a".to_string(),
+            player_2: "b".to_string(),
+            result: None,
+            date: None,
+            moves: vec![RecordedMove { col: 1, eval: None }],
+        };
+        let pgn = to_pgn(&record);
+        assert!(pgn.contains("[Setup \"#4/5/x4 o w3 d4\"]"));
+        assert_eq!(from_pgn(&pgn), Ok(record));
+        assert!(from_pgn("[Rows \"6\"]\n[Cols \"7\"]\n[Setup \"#4/5/x4 o w3\"]\n[Result \"*\"]\n\n*").is_err());
+    }
+
+    #[test]
+    fn pgn_rejects_malformed_headers() {
+        assert!(from_pgn("[Rows 6]\n[Cols \"7\"]\n[Result \"*\"]\n\n*").is_err());
+        assert!(from_pgn("[Rows \"6\"]\n[Result \"*\"]\n\n*").is_err());
+        assert!(from_pgn("[Rows \"6\"]\n[Cols \"7\"]\n[Result \"2-0\"]\n\n*").is_err());
+        assert!(from_pgn("[Rows \"six\"]\n[Cols \"7\"]\n[Result \"*\"]\n\n*").is_err());
+        assert!(from_pgn("[Rows \"6\"]\n[Cols \"7\"]\n[Result \"*\"]\n\n1. 9 *").is_err());
+    }
+}
@@ -0,0 +1,66 @@
+use std::cell::RefCell;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// The random number generator shared by every randomized component (random agents, random
+/// positions, self-play). Falls back to `thread_rng` until one is installed. The generator is
+/// per thread, so seeding it in one test does not leak into tests running alongside it.
+pub type GameRng = Box<dyn RngCore>;
+
+thread_local! {
+    static GAME_RNG: RefCell<Option<GameRng>> = RefCell::new(None);
+}
+
+pub fn set_rng(rng: GameRng) {
+    GAME_RNG.with(|cell| *cell.borrow_mut() = Some(rng));
+}
+
+/// Installs a `ChaCha8Rng` seeded with `seed`, making the whole session reproducible.
+pub fn seed(seed: u64) {
+    set_rng(Box::new(ChaCha8Rng::seed_from_u64(seed)));
+}
+
+/// Reverts to the unseeded `thread_rng`.
+pub fn reset_rng() {
+    GAME_RNG.with(|cell| *cell.borrow_mut() = None);
+}
+
+pub fn with_rng<T>(f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+    GAME_RNG.with(|cell| match cell.borrow_mut().as_mut() {
+        Some(rng) => f(rng.as_mut()),
+        None => f(&mut rand::thread_rng()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{Agent, MinMaxAgent, RandomMover};
+    use crate::game_logic::{get_random_position, play, result, GameState, Move, Player};
+    use crate::rng::{reset_rng, seed};
+
+    fn session() -> (Vec<Move>, GameState) {
+        let mut random_mover = RandomMover::new();
+        let moves = (0..20).map(|_| random_mover.next_move(&GameState::new())).collect();
+        let mut gs = get_random_position(&GameState::new(), 6);
+        let mut minmax = MinMaxAgent::new_with_depth(2);
+        while result(&gs).is_none() {
+            let mov = match gs.turn {
+                Player::P1 => minmax.next_move(&gs),
+                Player::P2 => random_mover.next_move(&gs),
+            };
+            gs = play(mov, &gs).unwrap();
+        }
+        (moves, gs)
+    }
+
+    #[test]
+    fn seeded_sessions_are_reproducible() {
+        seed(209);
+        let (first_moves, first_game) = session();
+        seed(209);
+        let (second_moves, second_game) = session();
+        reset_rng();
+        assert_eq!(first_moves, second_moves);
+        assert!(first_game == second_game);
+    }
+}
@@ -0,0 +1,145 @@
+use std::io::{self, Write};
+use crate::game::{take_turn, Agent, MinMaxAgent};
+use crate::game_logic::{result, try_play, GameResult, GameState, Player};
+use crate::rng;
+
+/// How a finished game turned out for the player to move in one of its positions.
+fn label(res: GameResult, player: Player) -> &'static str {
+    match res.winner() {
+        Some(winner) if winner == player => "win",
+        Some(_) => "loss",
+        None => "draw",
+    }
+}
+
+/// How `generate_dataset` plays and labels its games.
+#[derive(Clone, Copy)]
+pub struct DatasetOptions<'a> {
+    pub rows: usize,
+    pub cols: usize,
+    /// The shared `rng` is seeded with this first, so agents that draw from it play the same
+    /// games every time.
+    pub seed: u64,
+    /// Adds a third field to each record with this agent's score for every column, e.g.
+    /// `2=+1.0;3=mate in 2`.
+    pub scorer: Option<&'a MinMaxAgent>,
+}
+
+impl Default for DatasetOptions<'_> {
+    fn default() -> Self {
+        Self {
+            rows: 6,
+            cols: 7,
+            seed: 0,
+            scorer: None,
+        }
+    }
+}
+
+/// Plays `games` games between `a` and `b`, with `a` starting the even-numbered ones, and writes a
+/// training record for every position a move was played from: the position in compact notation
+/// and the final result for the side to move (`win`, `loss` or `draw`), separated by a tab.
+/// Returns the number of records written, or an `InvalidData` error if an agent plays an illegal
+/// move.
+pub fn generate_dataset(a: &mut dyn Agent, b: &mut dyn Agent, games: usize, options: &DatasetOptions, out: &mut dyn Write) -> io::Result<usize> {
+    let DatasetOptions { rows, cols, seed, scorer } = *options;
+    rng::seed(seed);
+    let mut records = 0;
+    for game in 0..games {
+        let (p1, p2): (&mut dyn Agent, &mut dyn Agent) = if game % 2 == 0 { (&mut *a, &mut *b) } else { (&mut *b, &mut *a) };
+        let mut gs = GameState::new_with_size(rows, cols);
+        let mut positions = Vec::new();
+        let res = loop {
+            if let Some(res) = result(&gs) {
+                break res;
+            }
+            let turn = match gs.turn() {
+                Player::P1 => take_turn(&gs, p1, p2),
+                Player::P2 => take_turn(&gs, p2, p1),
+            };
+            match turn {
+                Ok(mov) => {
+                    let next = try_play(mov, &gs)
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("illegal move {:?}: {}", mov, err)))?;
+                    positions.push(gs);
+                    gs = next;
+                }
+                Err(res) => break res,
+            }
+        };
+        for gs in &positions {
+            write!(out, "{}\t{}", gs.to_string_compact(), label(res, gs.turn()))?;
+            if let Some(scorer) = scorer {
+                let scores: Vec<String> = scorer.analyze_all(gs).iter().map(|(mov, score)| format!("{}={}", mov.col(), score)).collect();
+                write!(out, "\t{}", scores.join(";"))?;
+            }
+            writeln!(out)?;
+        }
+        records += positions.len();
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use crate::game::{Agent, MinMaxAgent, RandomMover};
+    use crate::game_logic::{disc_count, get_legal, GameState, Move};
+    use crate::selfplay::{generate_dataset, DatasetOptions};
+
+    /// Plays the top left cell, which is never legal on a fresh board.
+    struct Cheater;
+
+    impl Agent for Cheater {
+        fn next_move(&mut self, _gs: &GameState) -> Move {
+            Move::new(0, 0)
+        }
+    }
+
+    #[test]
+    fn one_record_per_move() {
+        let options = DatasetOptions { seed: 328, ..DatasetOptions::default() };
+        let mut out = Vec::new();
+        let records = generate_dataset(&mut RandomMover::new(), &mut MinMaxAgent::new_with_depth(2), 4, &options, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<(GameState, &str)> = text.lines().map(|line| {
+            let (compact, label) = line.split_once('\t').unwrap();
+            (GameState::from_compact(compact).unwrap(), label)
+        }).collect();
+        assert_eq!(lines.len(), records);
+        let mut games = 0;
+        for (i, (gs, label)) in lines.iter().enumerate() {
+            assert!(["win", "loss", "draw"].contains(label), "{}", label);
+            if disc_count(gs) == 0 {
+                games += 1;
+            } else {
+                // Within a game every record follows from the one before it by a single move.
+                assert_eq!(disc_count(gs), disc_count(&lines[i - 1].0) + 1);
+            }
+        }
+        assert_eq!(games, 4);
+
+        let mut again = Vec::new();
+        generate_dataset(&mut RandomMover::new(), &mut MinMaxAgent::new_with_depth(2), 4, &options, &mut again).unwrap();
+        assert_eq!(String::from_utf8(again).unwrap(), text);
+
+        let mut scored = Vec::new();
+        let scorer = MinMaxAgent::new_with_depth(2);
+        let records = generate_dataset(&mut RandomMover::new(), &mut RandomMover::new(), 1, &DatasetOptions { rows: 4, cols: 4, seed: 1, scorer: Some(&scorer) }, &mut scored).unwrap();
+        let text = String::from_utf8(scored).unwrap();
+        assert_eq!(text.lines().count(), records);
+        for line in text.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let gs = GameState::from_compact(fields[0]).unwrap();
+            assert_eq!(fields[2].split(';').count(), get_legal(&gs).len(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn illegal_moves_are_errors() {
+        let mut out = Vec::new();
+        let err = generate_dataset(&mut Cheater, &mut RandomMover::new(), 1, &DatasetOptions::default(), &mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,381 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use crate::game::Agent;
+use crate::game_logic::{center_out, children, get_legal, has_legal_move, next_turn, no_wins_possible, result, utility, winning_moves, GameResult, GameState, Move, Player};
+
+/// A solved value from the side to move's perspective, with the number of plies until the game
+/// is decided (a win, or a draw once neither player can complete a line any more).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Outcome {
+    value: i8,
+    distance: u32,
+}
+
+impl Outcome {
+    /// Wins are better sooner and losses later, and a quicker draw is preferred over a longer one.
+    fn rank(&self) -> (i8, i64) {
+        match self.value {
+            -1 => (-1, self.distance as i64),
+            value => (value, -(self.distance as i64)),
+        }
+    }
+
+    fn cmp_rank(&self, other: &Outcome) -> Ordering {
+        self.rank().cmp(&other.rank())
+    }
+
+    fn as_result(&self, gs: &GameState) -> GameResult {
+        match self.value {
+            1 => GameResult::Win(gs.turn),
+            -1 => GameResult::Win(next_turn(gs.turn)),
+            _ => GameResult::Draw,
+        }
+    }
+}
+
+fn solve_node(gs: &GameState, memo: &mut HashMap<GameState, Outcome>) -> Outcome {
+    match result(gs) {
+        // The previous player made the last move, so any win on the board is theirs.
+        Some(GameResult::Win(_)) => return Outcome { value: -1, distance: 0 },
+        Some(GameResult::Draw) => return Outcome { value: 0, distance: 0 },
+        // With every open column disabled the game stands still, which `Game` scores as a draw.
+        None if no_wins_possible(gs) || !has_legal_move(gs) => return Outcome { value: 0, distance: 0 },
+        None => {}
+    }
+    if !winning_moves(gs, gs.turn).is_empty() {
+        return Outcome { value: 1, distance: 1 };
+    }
+    if let Some(outcome) = memo.get(gs) {
+        return *outcome;
+    }
+    let best = children(gs)
+        .map(|(_, child)| {
+            let child = solve_node(&child, memo);
+            Outcome { value: -child.value, distance: child.distance + 1 }
+        })
+        .max_by(|a, b| a.cmp_rank(b))
+        .unwrap();
+    memo.insert(gs.clone(), best);
+    best
+}
+
+/// The game-theoretic result of `gs` under perfect play and the number of plies until it is
+/// decided. The search is exhaustive, so it is only practical on small or nearly full boards.
+pub fn solve(gs: &GameState) -> (GameResult, u32) {
+    let outcome = solve_node(gs, &mut HashMap::new());
+    (outcome.as_result(gs), outcome.distance)
+}
+
+/// The move perfect play chooses, preferring the quickest win, the quickest forced draw and
+/// the slowest loss.
+pub fn solve_move(gs: &GameState) -> Option<(Move, GameResult, u32)> {
+    if result(gs).is_some() {
+        return None;
+    }
+    if let Some(mov) = winning_moves(gs, gs.turn).first() {
+        return Some((*mov, GameResult::Win(gs.turn), 1));
+    }
+    let mut memo = HashMap::new();
+    children(gs)
+        .map(|(mov, child)| {
+            let child = solve_node(&child, &mut memo);
+            (mov, Outcome { value: -child.value, distance: child.distance + 1 })
+        })
+        .max_by(|(_, a), (_, b)| a.cmp_rank(b))
+        .map(|(mov, outcome)| (mov, outcome.as_result(gs), outcome.distance))
+}
+
+/// Beyond the length of any game, so that `SOLVED - plies` is a win in `plies` plies.
+const SOLVED: i32 = 10_000;
+
+/// A child's score as its parent sees it: the sign flips and a mate is one ply further away.
+fn solved_to_parent(score: i32) -> i32 {
+    match score {
+        0 => 0,
+        score if score > 0 => 1 - score,
+        score => -1 - score,
+    }
+}
+
+/// The inverse of `solved_to_parent`, for passing the parent's window down.
+fn solved_from_parent(score: i32) -> i32 {
+    match score {
+        0 => 0,
+        score if score > 0 => -1 - score,
+        score => 1 - score,
+    }
+}
+
+fn solve_window(gs: &GameState, mut alpha: i32, mut beta: i32, bounds: &mut HashMap<Vec<u64>, (i32, i32)>) -> i32 {
+    match result(gs) {
+        // The previous player made the last move, so any win on the board is theirs.
+        Some(GameResult::Win(_)) => return -SOLVED,
+        Some(GameResult::Draw) => return 0,
+        None if no_wins_possible(gs) || !has_legal_move(gs) => return 0,
+        None => {}
+    }
+    if !winning_moves(gs, gs.turn).is_empty() {
+        return SOLVED - 1;
+    }
+    let (_, key) = gs.canonical_key();
+    let (mut lower, mut upper) = bounds.get(&key).copied().unwrap_or((-SOLVED, SOLVED));
+    if lower >= beta || lower == upper {
+        return lower;
+    }
+    if upper <= alpha {
+        return upper;
+    }
+    alpha = alpha.max(lower);
+    beta = beta.min(upper);
+    let (alpha_orig, beta_orig) = (alpha, beta);
+    let order = center_out(gs.lanes());
+    let mut moves: Vec<(Move, GameState)> = children(gs).collect();
+    moves.sort_by_key(|(mov, _)| order.iter().position(|&col| col == mov.col()));
+    let mut best = -SOLVED;
+    for (_, child) in moves {
+        let value = solved_to_parent(solve_window(&child, solved_from_parent(beta), solved_from_parent(alpha), bounds));
+        best = best.max(value);
+        alpha = alpha.max(value);
+        if alpha >= beta {
+            break;
+        }
+    }
+    if best <= alpha_orig {
+        upper = best;
+    } else if best >= beta_orig {
+        lower = best;
+    } else {
+        (lower, upper) = (best, best);
+    }
+    bounds.insert(key, (lower, upper));
+    best
+}
+
+/// The game-theoretic result of `gs` and the distance to mate in plies, with the winner
+/// hurrying and the loser holding out. Unlike `solve` it prunes with alpha-beta, which makes it
+/// much faster on late positions, but it does not tell draws apart: their distance is 0.
+pub fn solve_position(gs: &GameState) -> (GameResult, u32) {
+    let score = solve_window(gs, -SOLVED, SOLVED, &mut HashMap::new());
+    match score {
+        0 => (GameResult::Draw, 0),
+        score if score > 0 => (GameResult::Win(gs.turn), (SOLVED - score) as u32),
+        score => (GameResult::Win(next_turn(gs.turn)), (SOLVED + score) as u32),
+    }
+}
+
+/// How good `result` is for `player`.
+fn rank_for(result: GameResult, player: Player) -> i8 {
+    match result {
+        GameResult::Win(winner) if winner == player => 1,
+        GameResult::Win(_) => -1,
+        GameResult::Draw => 0,
+    }
+}
+
+fn tablebase_node(gs: &GameState, table: &mut HashMap<GameState, GameResult>) -> GameResult {
+    let canonical = gs.canonical();
+    if let Some(result) = table.get(&canonical) {
+        return *result;
+    }
+    let value = result(gs).unwrap_or_else(|| children(gs)
+        .map(|(_, child)| tablebase_node(&child, table))
+        .max_by_key(|result| rank_for(*result, gs.turn))
+        .unwrap());
+    table.insert(canonical, value);
+    value
+}
+
+/// The game-theoretic result of every position reachable from the empty `rows` x `cols` board,
+/// finished ones included. Positions are stored in their canonical form, so look them up with
+/// `gs.canonical()`. Every position is visited, so this is only practical for boards up to about
+/// 5x5.
+pub fn build_tablebase(rows: usize, cols: usize, win_len: usize) -> HashMap<GameState, GameResult> {
+    let mut table = HashMap::new();
+    tablebase_node(&GameState::new_with_win_len(rows, cols, win_len), &mut table);
+    table
+}
+
+/// Plays perfectly from a tablebase: a move that keeps a won game won, or failing that a drawn
+/// one. Positions the tablebase does not know count as draws.
+pub struct TablebaseAgent {
+    table: HashMap<GameState, GameResult>,
+}
+
+impl TablebaseAgent {
+    pub fn new(table: HashMap<GameState, GameResult>) -> Self {
+        Self { table }
+    }
+
+    pub fn result(&self, gs: &GameState) -> Option<GameResult> {
+        self.table.get(&gs.canonical()).copied()
+    }
+}
+
+impl Agent for TablebaseAgent {
+    fn label(&self) -> String {
+        "tablebase".to_string()
+    }
+
+    fn next_move(&mut self, gs: &GameState) -> Move {
+        children(gs)
+            .max_by_key(|(_, child)| rank_for(self.result(child).unwrap_or(GameResult::Draw), gs.turn))
+            .map(|(mov, _)| mov)
+            .unwrap_or_else(|| get_legal(gs)[0])
+    }
+}
+
+/// The positions whose static `utility` points the wrong way: towards the loser of a solved win,
+/// or at no one at all. A finite eval is never held against a solved draw. Each position comes
+/// with its eval and solved result, which is useful for finding where the heuristic misleads.
+pub fn eval_vs_solver_disagreements(positions: &[GameState]) -> Vec<(GameState, f32, GameResult)> {
+    let mut memo = HashMap::new();
+    positions.iter()
+        .filter_map(|gs| {
+            let eval = utility(gs);
+            let solved = solve_node(gs, &mut memo).as_result(gs);
+            let agrees = match solved {
+                GameResult::Win(Player::P1) => eval > 0.0,
+                GameResult::Win(Player::P2) => eval < 0.0,
+                GameResult::Draw => eval.is_finite(),
+            };
+            (!agrees).then(|| (gs.clone(), eval, solved))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{Agent, RandomMover};
+    use crate::game_logic::{get_random_position, play, result, GameResult, GameState, Move, Player};
+    use crate::rng;
+    use crate::solve::{build_tablebase, eval_vs_solver_disagreements, solve, solve_move, solve_position, TablebaseAgent};
+
+    #[test]
+    fn prefers_shorter_forced_draw() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [2,0,0,1,1,0,2],
+                [2,0,1,2,2,1,1],
+                [1,0,2,2,1,1,2],
+                [2,0,1,1,2,2,1],
+                [1,2,2,2,1,1,1],
+                [2,1,2,1,2,2,1]
+            ]
+        );
+        assert_eq!(solve(&play(Move::new(0, 2), &gs).unwrap()), (GameResult::Draw, 5));
+        assert_eq!(solve(&play(Move::new(0, 5), &gs).unwrap()), (GameResult::Draw, 3));
+        assert_eq!(solve(&gs), (GameResult::Draw, 4));
+        let (mov, result, distance) = solve_move(&gs).unwrap();
+        assert_ne!(mov, Move::new(0, 2));
+        assert_eq!((result, distance), (GameResult::Draw, 4));
+    }
+
+    #[test]
+    fn solves_forced_win() {
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,2,0,0,0,0],
+                [0,0,2,0,0,0,0],
+                [0,0,2,1,1,1,0]
+            ]
+        );
+        assert_eq!(solve_move(&gs), Some((Move::new(5, 6), GameResult::Win(Player::P1), 1)));
+    }
+
+    #[test]
+    fn solve_position_agrees_with_solve() {
+        assert_eq!(solve_position(&GameState::new_with_size(4, 4)), (GameResult::Draw, 0));
+        let gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,0,0,0,0,0],
+                [0,0,2,0,0,0,0],
+                [0,0,2,0,0,0,0],
+                [0,0,2,1,1,1,0]
+            ]
+        );
+        assert_eq!(solve_position(&gs), (GameResult::Win(Player::P1), 1));
+        // Missing the win lets P2 complete column 2.
+        assert_eq!(solve_position(&play(Move::new(5, 1), &gs).unwrap()), (GameResult::Win(Player::P2), 1));
+        rng::seed(315);
+        let mut decided = 0;
+        for i in 0..60 {
+            let gs = get_random_position(&GameState::new(), 22 + i % 12);
+            let (expected, distance) = solve(&gs);
+            let solved = solve_position(&gs);
+            if expected == GameResult::Draw {
+                assert_eq!(solved, (GameResult::Draw, 0), "{}", gs);
+            } else {
+                assert_eq!(solved, (expected, distance), "{}", gs);
+                decided += 1;
+            }
+        }
+        assert!(decided > 0);
+    }
+
+    /// On random 4x5 positions the potential-wins heuristic misjudges about a third of the
+    /// positions, mostly ones decided by a forced sequence it cannot see.
+    const MAX_DISAGREEMENT_RATE: f32 = 0.4;
+
+    #[test]
+    fn eval_disagreements_stay_rare() {
+        rng::seed(220);
+        let empty = GameState::new_from_board(vec![vec![0; 5]; 4]);
+        let positions: Vec<GameState> = (0..200)
+            .map(|i| get_random_position(&empty, 8 + i % 8))
+            .filter(|gs| result(gs).is_none())
+            .collect();
+        let disagreements = eval_vs_solver_disagreements(&positions);
+        let rate = disagreements.len() as f32 / positions.len() as f32;
+        assert!(rate < MAX_DISAGREEMENT_RATE, "disagreement rate {}", rate);
+    }
+
+    #[test]
+    fn tablebase_plays_won_positions_out() {
+        let table = build_tablebase(4, 4, 4);
+        let empty = GameState::new_with_size(4, 4);
+        // 4x4 connect-4 is a draw.
+        assert_eq!(table[&empty], GameResult::Draw);
+        assert_eq!(table[&empty], solve(&empty).0);
+        let won: Vec<GameState> = table.iter()
+            .filter(|(gs, value)| result(gs).is_none() && **value == GameResult::Win(gs.turn))
+            .map(|(gs, _)| gs.clone())
+            .take(100)
+            .collect();
+        assert!(!won.is_empty());
+        rng::seed(297);
+        let mut agent = TablebaseAgent::new(table);
+        let mut opponent = RandomMover::new();
+        for start in won {
+            let mut gs = start.clone();
+            while result(&gs).is_none() {
+                let mov = if gs.turn == start.turn { agent.next_move(&gs) } else { opponent.next_move(&gs) };
+                gs = play(mov, &gs).unwrap();
+            }
+            assert_eq!(result(&gs), Some(GameResult::Win(start.turn)), "{}", start);
+        }
+    }
+
+    #[test]
+    fn flags_misleading_eval() {
+        let mut gs = GameState::new_from_board(
+            vec2d![
+                [0,0,0,0,0],
+                [0,0,0,0,2],
+                [1,1,0,0,2],
+                [1,1,0,0,2]
+            ]
+        );
+        gs.turn = Player::P2;
+        let disagreements = eval_vs_solver_disagreements(&[gs.clone()]);
+        assert_eq!(disagreements.len(), 1);
+        let (flagged, eval, solved) = &disagreements[0];
+        assert_eq!(flagged, &gs);
+        assert!(*eval > 0.0);
+        assert_eq!(*solved, GameResult::Win(Player::P2));
+    }
+}
@@ -0,0 +1,102 @@
+use crate::game::{take_turn, Agent};
+use crate::game_logic::{has_legal_move, next_turn, play, result, GameResult, GameState, Player};
+use crate::rng;
+
+/// An agent's `label`, which tells apart agents of the same kind configured differently.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct AgentId(pub String);
+
+/// The outcome of a match from the point of view of its two agents, `a` and `b`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MatchResult {
+    pub a: AgentId,
+    pub b: AgentId,
+    pub a_wins: usize,
+    pub b_wins: usize,
+    pub draws: usize,
+}
+
+impl MatchResult {
+    pub fn games(&self) -> usize {
+        self.a_wins + self.b_wins + self.draws
+    }
+}
+
+/// Plays one game without rendering or prompting and returns its result. As in `Game`, an agent
+/// that plays an illegal move forfeits.
+pub fn play_game(p1: &mut dyn Agent, p2: &mut dyn Agent, rows: usize, cols: usize) -> GameResult {
+    let mut gs = GameState::new_with_size(rows, cols);
+    loop {
+        if let Some(res) = result(&gs) {
+            return res;
+        }
+        if !has_legal_move(&gs) {
+            return GameResult::Draw;
+        }
+        let turn = match gs.turn() {
+            Player::P1 => take_turn(&gs, p1, p2),
+            Player::P2 => take_turn(&gs, p2, p1),
+        };
+        match turn {
+            Ok(mov) => match play(mov, &gs) {
+                Some(next) => gs = next,
+                None => return GameResult::Win(next_turn(gs.turn())),
+            },
+            Err(res) => return res,
+        }
+    }
+}
+
+/// Plays `games` games between `a` and `b`, with `a` starting the even-numbered ones. `crate::rng`
+/// is seeded with `seed` first and left that way, so agents that draw from it play the same match
+/// for the same seed.
+pub fn run_match(a: &mut dyn Agent, b: &mut dyn Agent, games: usize, rows: usize, cols: usize, seed: u64) -> MatchResult {
+    rng::seed(seed);
+    let mut outcome = MatchResult { a: AgentId(a.label()), b: AgentId(b.label()), ..MatchResult::default() };
+    for game in 0..games {
+        let a_player = if game % 2 == 0 { Player::P1 } else { Player::P2 };
+        let res = match a_player {
+            Player::P1 => play_game(a, b, rows, cols),
+            Player::P2 => play_game(b, a, rows, cols),
+        };
+        match res {
+            GameResult::Win(winner) if winner == a_player => outcome.a_wins += 1,
+            GameResult::Win(_) => outcome.b_wins += 1,
+            GameResult::Draw => outcome.draws += 1,
+        }
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{Agent, MinMaxAgent, RandomMover};
+    use crate::game_logic::{GameResult, GameState, Move, Player};
+    use crate::rng::reset_rng;
+    use crate::tournament::{play_game, run_match};
+
+    #[test]
+    fn min_max_beats_random_mover() {
+        let outcome = run_match(&mut RandomMover::new(), &mut MinMaxAgent::new_with_depth(4), 6, 6, 7, 293);
+        let again = run_match(&mut RandomMover::new(), &mut MinMaxAgent::new_with_depth(4), 6, 6, 7, 293);
+        reset_rng();
+        assert_eq!(outcome, again);
+        assert_eq!(outcome.games(), 6);
+        assert_eq!((outcome.a.0.as_str(), outcome.b.0.as_str()), ("random", "minmax-d4-window"));
+        assert!(outcome.b_wins > outcome.games() / 2, "{:?}", outcome);
+    }
+
+    struct Cheater;
+
+    impl Agent for Cheater {
+        fn next_move(&mut self, _gs: &GameState) -> Move {
+            Move::new(0, 0)
+        }
+    }
+
+    #[test]
+    fn illegal_moves_forfeit() {
+        assert_eq!(play_game(&mut Cheater, &mut RandomMover::new(), 6, 7), GameResult::Win(Player::P2));
+        assert_eq!(play_game(&mut MinMaxAgent::new_with_depth(2), &mut Cheater, 6, 7), GameResult::Win(Player::P1));
+    }
+}
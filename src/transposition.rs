@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use crate::game_logic::{disc_count, packed_board, GameState};
+
+/// A stored search value. A search that fails high or low only learns a bound on the true value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bound {
+    Exact(f32),
+    LowerBound(f32),
+    UpperBound(f32),
+}
+
+impl Bound {
+    pub fn value(&self) -> f32 {
+        match self {
+            Bound::Exact(value) | Bound::LowerBound(value) | Bound::UpperBound(value) => *value,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableEntry {
+    /// The full packed position, compared on every probe so that two positions sharing a hash
+    /// never read each other's values.
+    verification: Vec<u64>,
+    /// Discs on the board, which only grows during a game without Pop Out.
+    pub discs: usize,
+    pub depth: i32,
+    pub bound: Bound,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TranspositionTable {
+    entries: HashMap<u64, TableEntry>,
+}
+
+impl TranspositionTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn probe(&self, key: u64, gs: &GameState) -> Option<&TableEntry> {
+        self.probe_packed(key, &packed_board(gs))
+    }
+
+    pub fn store(&mut self, key: u64, gs: &GameState, depth: i32, bound: Bound) {
+        self.store_packed(key, packed_board(gs), disc_count(gs), depth, bound);
+    }
+
+    /// `probe` for a position that is already packed, as `GameState::canonical_key` returns it.
+    pub fn probe_packed(&self, key: u64, packed: &[u64]) -> Option<&TableEntry> {
+        self.entries.get(&key).filter(|entry| entry.verification == packed)
+    }
+
+    pub fn store_packed(&mut self, key: u64, packed: Vec<u64>, discs: usize, depth: i32, bound: Bound) {
+        self.entries.insert(key, TableEntry {
+            verification: packed,
+            discs,
+            depth,
+            bound,
+        });
+    }
+
+    /// Drops the positions a game that has reached `gs` can never return to: those with fewer
+    /// discs. Under Pop Out discs come off the board again, so everything is dropped.
+    pub fn retain_reachable(&mut self, gs: &GameState) {
+        if gs.pop_out() {
+            self.entries.clear();
+            return;
+        }
+        let discs = disc_count(gs);
+        self.entries.retain(|_, entry| entry.discs >= discs);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game_logic::{play, position_hash, GameState, Move};
+    use crate::transposition::{Bound, TranspositionTable};
+
+    #[test]
+    fn colliding_keys_are_rejected() {
+        let gs = GameState::new();
+        let left = play(Move::new(5, 0), &gs).unwrap();
+        let right = play(Move::new(5, 6), &gs).unwrap();
+        let mut table = TranspositionTable::new();
+        let key = position_hash(&left);
+        table.store(key, &left, 4, Bound::Exact(3.0));
+        assert_eq!(table.probe(key, &left).map(|entry| entry.bound), Some(Bound::Exact(3.0)));
+        assert!(table.probe(key, &right).is_none());
+    }
+
+    #[test]
+    fn mirrored_positions_share_a_key() {
+        let gs = GameState::new();
+        let left = play(Move::new(5, 1), &gs).unwrap();
+        let left = play(Move::new(5, 3), &left).unwrap();
+        let right = play(Move::new(5, 5), &gs).unwrap();
+        let right = play(Move::new(5, 3), &right).unwrap();
+        assert_ne!(position_hash(&left), position_hash(&right));
+        assert_eq!(left.mirrored(), right);
+        assert_eq!(left.canonical(), right.canonical());
+        assert_eq!(position_hash(&left.canonical()), position_hash(&right.canonical()));
+        let mut table = TranspositionTable::new();
+        let canonical = left.canonical();
+        table.store(position_hash(&canonical), &canonical, 2, Bound::Exact(1.0));
+        let canonical = right.canonical();
+        assert!(table.probe(position_hash(&canonical), &canonical).is_some());
+    }
+
+    #[test]
+    fn unreachable_entries_are_dropped() {
+        let gs = GameState::new();
+        let child = play(Move::new(5, 3), &gs).unwrap();
+        let mut table = TranspositionTable::new();
+        table.store(position_hash(&gs), &gs, 2, Bound::Exact(1.0));
+        table.store(position_hash(&child), &child, 2, Bound::LowerBound(1.0));
+        table.retain_reachable(&child);
+        assert_eq!(table.len(), 1);
+        assert!(table.probe(position_hash(&child), &child).is_some());
+        let mut popping = child.clone();
+        popping.set_pop_out(true);
+        table.retain_reachable(&popping);
+        assert!(table.is_empty());
+    }
+}